@@ -0,0 +1,557 @@
+// Trains the evaluation network on the CPU and writes it in the format the
+// engine loads with the `EvalFile` option.
+//
+//     trainer <data> <output> [--hidden N] [--epochs N] [--batch-size N]
+//             [--lr RATE] [--optimizer adam|sgd] [--lambda WEIGHT] [--seed N]
+//
+// Each line of the data file is one position: `<fen> | <score> | <result>`,
+// with the score in centipawns and the result as 1.0, 0.5 or 0.0 (or 1-0,
+// 1/2-1/2, 0-1), both from White's point of view. The network learns to
+// predict a blend of the result and the win probability of the score, with
+// `lambda` the weight of the result, and the loss is the squared error of
+// its own win probability. Inputs are numbered by the engine's
+// `feature_index`, so training and inference always agree on them.
+
+use pato::board::state::{Color, PieceType, State};
+use pato::eval::nnue::{feature_index, Network, INPUTS, MAX_HIDDEN, QA, QB, SCALE};
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+use strum::IntoEnumIterator;
+
+const MAX_PIECES: usize = 32;
+// Scores are win probabilities of sigmoid(score / EVAL_SCALE).
+const EVAL_SCALE: f32 = 400.0;
+// Weights and biases stay within this, so that they fit an i16 once
+// quantized.
+const WEIGHT_LIMIT: f32 = 1.98;
+
+const USAGE: &str = "usage: trainer <data> <output> [--hidden N] [--epochs N] [--batch-size N] \
+                     [--lr RATE] [--optimizer adam|sgd] [--lambda WEIGHT] [--seed N]";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Optimizer {
+    /// Stochastic gradient descent with momentum.
+    Sgd,
+    Adam,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    data: String,
+    output: String,
+    hidden: usize,
+    epochs: usize,
+    batch_size: usize,
+    learning_rate: f32,
+    optimizer: Optimizer,
+    lambda: f32,
+    seed: u64,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let [data, output, flags @ ..] = args else {
+        return Err(USAGE.to_string());
+    };
+    let mut options = Options {
+        data: data.clone(),
+        output: output.clone(),
+        hidden: 256,
+        epochs: 10,
+        batch_size: 16384,
+        learning_rate: 0.001,
+        optimizer: Optimizer::Adam,
+        lambda: 0.5,
+        seed: 1,
+    };
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("missing value for {flag}"))?;
+        let invalid = || format!("invalid value '{value}' for {flag}");
+        match flag.as_str() {
+            "--hidden" => options.hidden = value.parse().map_err(|_| invalid())?,
+            "--epochs" => options.epochs = value.parse().map_err(|_| invalid())?,
+            "--batch-size" => options.batch_size = value.parse().map_err(|_| invalid())?,
+            "--lr" => options.learning_rate = value.parse().map_err(|_| invalid())?,
+            "--lambda" => options.lambda = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--optimizer" => {
+                options.optimizer = match value.as_str() {
+                    "adam" => Optimizer::Adam,
+                    "sgd" => Optimizer::Sgd,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(format!("unknown option {flag}\n{USAGE}")),
+        }
+    }
+    if !(1..=MAX_HIDDEN).contains(&options.hidden) {
+        return Err(format!("--hidden must be between 1 and {MAX_HIDDEN}"));
+    }
+    if options.batch_size == 0 {
+        return Err("--batch-size must be positive".to_string());
+    }
+    if !(0.0..=1.0).contains(&options.lambda) {
+        return Err("--lambda must be between 0 and 1".to_string());
+    }
+    Ok(options)
+}
+
+/// A training position: the active inputs from the point of view of the
+/// side to move and of the other side, and the win probability to learn.
+struct Sample {
+    features: [[u16; MAX_PIECES]; 2],
+    count: usize,
+    target: f32,
+}
+
+fn parse_record(line: &str, lambda: f32) -> Result<Sample, String> {
+    let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+    let [fen, score, result] = fields[..] else {
+        return Err("expected '<fen> | <score> | <result>'".to_string());
+    };
+    let state = State::from_fen(fen).map_err(|error| format!("invalid FEN: {error}"))?;
+    let mut score: f32 = score
+        .parse()
+        .map_err(|_| format!("invalid score '{score}'"))?;
+    let mut result = match result {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        text => text
+            .parse()
+            .ok()
+            .filter(|result| (0.0..=1.0).contains(result))
+            .ok_or_else(|| format!("invalid result '{text}'"))?,
+    };
+
+    let side_to_move = state.side_to_move();
+    if side_to_move == Color::Black {
+        score = -score;
+        result = 1.0 - result;
+    }
+    let mut sample = Sample {
+        features: [[0; MAX_PIECES]; 2],
+        count: 0,
+        target: lambda * result + (1.0 - lambda) * sigmoid(score / EVAL_SCALE),
+    };
+    for color in Color::iter() {
+        for piece in PieceType::iter() {
+            for square in state.get_bitboard_for(piece, color).squares() {
+                if sample.count == MAX_PIECES {
+                    return Err("too many pieces".to_string());
+                }
+                for (side, perspective) in [side_to_move, side_to_move.opposite()]
+                    .into_iter()
+                    .enumerate()
+                {
+                    sample.features[side][sample.count] =
+                        feature_index(perspective, piece, color, square) as u16;
+                }
+                sample.count += 1;
+            }
+        }
+    }
+    Ok(sample)
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// xorshift64*, for the initial weights and the order of the samples.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in `[-range, range]`.
+    fn uniform(&mut self, range: f32) -> f32 {
+        let unit = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        (2.0 * unit - 1.0) * range
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, (self.next() % (i as u64 + 1)) as usize);
+        }
+    }
+}
+
+/// The network in floating point. All parameters are in one vector, laid
+/// out as in the weights file: feature weights by input, feature biases,
+/// output weights with the side to move first, and the output bias.
+struct FloatNetwork {
+    hidden: usize,
+    params: Vec<f32>,
+}
+
+impl FloatNetwork {
+    fn random(hidden: usize, rng: &mut Rng) -> FloatNetwork {
+        let mut network = FloatNetwork {
+            hidden,
+            params: vec![0.0; (INPUTS + 3) * hidden + 1],
+        };
+        let feature_range = 1.0 / (MAX_PIECES as f32).sqrt();
+        let output_range = 1.0 / (2.0 * hidden as f32).sqrt();
+        for index in 0..INPUTS * hidden {
+            network.params[index] = rng.uniform(feature_range);
+        }
+        for index in network.output_weights_at()..network.output_bias_at() {
+            network.params[index] = rng.uniform(output_range);
+        }
+        network
+    }
+
+    fn biases_at(&self) -> usize {
+        INPUTS * self.hidden
+    }
+
+    fn output_weights_at(&self) -> usize {
+        (INPUTS + 1) * self.hidden
+    }
+
+    fn output_bias_at(&self) -> usize {
+        (INPUTS + 3) * self.hidden
+    }
+
+    /// The raw output for `sample`, leaving the accumulators of both sides
+    /// in `accumulators`. Times `SCALE` it is the evaluation in centipawns.
+    fn forward(&self, sample: &Sample, accumulators: &mut [Vec<f32>; 2]) -> f32 {
+        let hidden = self.hidden;
+        let biases = &self.params[self.biases_at()..self.biases_at() + hidden];
+        let output_weights = &self.params[self.output_weights_at()..self.output_bias_at()];
+        let mut output = self.params[self.output_bias_at()];
+        for (side, accumulator) in accumulators.iter_mut().enumerate() {
+            accumulator.copy_from_slice(biases);
+            for &input in &sample.features[side][..sample.count] {
+                let start = input as usize * hidden;
+                for (value, weight) in accumulator
+                    .iter_mut()
+                    .zip(&self.params[start..start + hidden])
+                {
+                    *value += weight;
+                }
+            }
+            let weights = &output_weights[side * hidden..(side + 1) * hidden];
+            for (value, weight) in accumulator.iter().zip(weights) {
+                output += value.clamp(0.0, 1.0) * weight;
+            }
+        }
+        output
+    }
+
+    /// Adds the gradient of the loss on `sample` to `gradient` and returns
+    /// the loss.
+    fn backward(
+        &self,
+        sample: &Sample,
+        accumulators: &mut [Vec<f32>; 2],
+        gradient: &mut [f32],
+    ) -> f32 {
+        let hidden = self.hidden;
+        let scale = SCALE as f32 / EVAL_SCALE;
+        let prediction = sigmoid(self.forward(sample, accumulators) * scale);
+        let error = prediction - sample.target;
+        let output_gradient = 2.0 * error * prediction * (1.0 - prediction) * scale;
+
+        gradient[self.output_bias_at()] += output_gradient;
+        for (side, accumulator) in accumulators.iter().enumerate() {
+            let weights_at = self.output_weights_at() + side * hidden;
+            for (neuron, &value) in accumulator.iter().enumerate() {
+                gradient[weights_at + neuron] += output_gradient * value.clamp(0.0, 1.0);
+                // The clipped activation passes no gradient outside (0, 1).
+                if value <= 0.0 || value >= 1.0 {
+                    continue;
+                }
+                let neuron_gradient = output_gradient * self.params[weights_at + neuron];
+                gradient[self.biases_at() + neuron] += neuron_gradient;
+                for &input in &sample.features[side][..sample.count] {
+                    gradient[input as usize * hidden + neuron] += neuron_gradient;
+                }
+            }
+        }
+        error * error
+    }
+
+    fn quantize(&self) -> Network {
+        let quantize = |values: &[f32], scale: i32| -> Vec<i16> {
+            values
+                .iter()
+                .map(|value| (value * scale as f32).round() as i16)
+                .collect()
+        };
+        Network::new(
+            quantize(&self.params[..self.biases_at()], QA),
+            quantize(&self.params[self.biases_at()..self.output_weights_at()], QA),
+            quantize(
+                &self.params[self.output_weights_at()..self.output_bias_at()],
+                QB,
+            ),
+            (self.params[self.output_bias_at()] * (QA * QB) as f32).round() as i32,
+        )
+    }
+}
+
+struct OptimizerState {
+    kind: Optimizer,
+    learning_rate: f32,
+    // Momentum for SGD; first and second moments for Adam.
+    first: Vec<f32>,
+    second: Vec<f32>,
+    steps: i32,
+}
+
+impl OptimizerState {
+    const MOMENTUM: f32 = 0.9;
+    const BETA1: f32 = 0.9;
+    const BETA2: f32 = 0.999;
+    const EPSILON: f32 = 1e-8;
+
+    fn new(kind: Optimizer, learning_rate: f32, size: usize) -> OptimizerState {
+        OptimizerState {
+            kind,
+            learning_rate,
+            first: vec![0.0; size],
+            second: vec![0.0; size],
+            steps: 0,
+        }
+    }
+
+    fn step(&mut self, params: &mut [f32], gradient: &[f32]) {
+        self.steps += 1;
+        let rate = self.learning_rate;
+        match self.kind {
+            Optimizer::Sgd => {
+                for ((param, &grad), velocity) in
+                    params.iter_mut().zip(gradient).zip(&mut self.first)
+                {
+                    *velocity = Self::MOMENTUM * *velocity + grad;
+                    *param -= rate * *velocity;
+                }
+            }
+            Optimizer::Adam => {
+                let correction1 = 1.0 - Self::BETA1.powi(self.steps);
+                let correction2 = 1.0 - Self::BETA2.powi(self.steps);
+                for (((param, &grad), first), second) in params
+                    .iter_mut()
+                    .zip(gradient)
+                    .zip(&mut self.first)
+                    .zip(&mut self.second)
+                {
+                    *first = Self::BETA1 * *first + (1.0 - Self::BETA1) * grad;
+                    *second = Self::BETA2 * *second + (1.0 - Self::BETA2) * grad * grad;
+                    let first = *first / correction1;
+                    let second = *second / correction2;
+                    *param -= rate * first / (second.sqrt() + Self::EPSILON);
+                }
+            }
+        }
+        // The output bias is quantized to an i32 and needs no limit.
+        let (weights, _) = params.split_at_mut(params.len() - 1);
+        for weight in weights {
+            *weight = weight.clamp(-WEIGHT_LIMIT, WEIGHT_LIMIT);
+        }
+    }
+}
+
+/// One pass over `samples` in random order. Returns the mean loss.
+fn train_epoch(
+    network: &mut FloatNetwork,
+    optimizer: &mut OptimizerState,
+    samples: &mut [Sample],
+    batch_size: usize,
+    rng: &mut Rng,
+) -> f32 {
+    rng.shuffle(samples);
+    let mut accumulators = [vec![0.0; network.hidden], vec![0.0; network.hidden]];
+    let mut gradient = vec![0.0; network.params.len()];
+    let mut total_loss = 0.0;
+    for batch in samples.chunks(batch_size) {
+        gradient.fill(0.0);
+        for sample in batch {
+            total_loss += network.backward(sample, &mut accumulators, &mut gradient);
+        }
+        let scale = 1.0 / batch.len() as f32;
+        gradient.iter_mut().for_each(|grad| *grad *= scale);
+        optimizer.step(&mut network.params, &gradient);
+    }
+    total_loss / samples.len() as f32
+}
+
+fn load_samples(options: &Options) -> Vec<Sample> {
+    let text = fs::read_to_string(&options.data).unwrap_or_else(|error| {
+        eprintln!("cannot read {}: {error}", options.data);
+        process::exit(1);
+    });
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_record(line, options.lambda) {
+            Ok(sample) => samples.push(sample),
+            Err(error) => {
+                if skipped < 10 {
+                    eprintln!("{}:{}: {error}", options.data, number + 1);
+                }
+                skipped += 1;
+            }
+        }
+    }
+    if skipped > 0 {
+        eprintln!("skipped {skipped} invalid records");
+    }
+    samples
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        process::exit(2);
+    });
+
+    let mut samples = load_samples(&options);
+    if samples.is_empty() {
+        eprintln!("no positions to train on");
+        process::exit(1);
+    }
+    println!("training on {} positions", samples.len());
+
+    let mut rng = Rng(options.seed.max(1));
+    let mut network = FloatNetwork::random(options.hidden, &mut rng);
+    let mut optimizer = OptimizerState::new(
+        options.optimizer,
+        options.learning_rate,
+        network.params.len(),
+    );
+    for epoch in 1..=options.epochs {
+        let start = Instant::now();
+        let loss = train_epoch(
+            &mut network,
+            &mut optimizer,
+            &mut samples,
+            options.batch_size,
+            &mut rng,
+        );
+        // Saved after every epoch, so that an interrupted run keeps its work.
+        if let Err(error) = network.quantize().save(&options.output) {
+            eprintln!("cannot write {}: {error}", options.output);
+            process::exit(1);
+        }
+        println!(
+            "epoch {epoch}/{}: loss {loss:.6}, {:.1}s",
+            options.epochs,
+            start.elapsed().as_secs_f32()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pato::board::state::Square;
+    use pato::eval::nnue::Accumulator;
+    use std::sync::Arc;
+
+    const FENS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1",
+    ];
+
+    #[test]
+    fn test_parse_options() {
+        let args: Vec<String> = [
+            "data.txt",
+            "net.bin",
+            "--hidden",
+            "32",
+            "--optimizer",
+            "sgd",
+        ]
+        .map(String::from)
+        .to_vec();
+        let options = parse_options(&args).unwrap();
+        assert_eq!(options.hidden, 32);
+        assert_eq!(options.optimizer, Optimizer::Sgd);
+        assert_eq!(options.epochs, 10);
+        assert!(parse_options(&args[..1]).is_err());
+        let bad = [&args[..2], &["--lambda".to_string(), "2".to_string()]].concat();
+        assert!(parse_options(&bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_record() {
+        // Black to move: the score and result are turned around.
+        let sample = parse_record(&format!("{} | 400 | 1-0", FENS[0]), 0.5).unwrap();
+        assert_eq!(sample.count, 32);
+        let expected = 0.5 * 0.0 + 0.5 * sigmoid(-1.0);
+        assert!((sample.target - expected).abs() < 1e-6);
+        // The black king on e8 is the side to move's own king on e1.
+        let king = feature_index(Color::Black, PieceType::King, Color::Black, Square::E8);
+        assert!(sample.features[0][..32].contains(&(king as u16)));
+
+        assert!(parse_record("8/8/8/8 w - - 0 1 | 0 | 0.5", 0.5).is_err());
+        assert!(parse_record(&format!("{} | 10", FENS[1]), 0.5).is_err());
+        assert!(parse_record(&format!("{} | 10 | 2", FENS[1]), 0.5).is_err());
+    }
+
+    #[test]
+    fn test_quantized_network_matches_float_network() {
+        let mut rng = Rng(7);
+        let network = FloatNetwork::random(16, &mut rng);
+        let quantized = Arc::new(network.quantize());
+        let mut accumulators = [vec![0.0; 16], vec![0.0; 16]];
+        for fen in FENS {
+            let sample = parse_record(&format!("{fen} | 0 | 0.5"), 0.5).unwrap();
+            let expected = network.forward(&sample, &mut accumulators) * SCALE as f32;
+            let state = State::from_fen(fen).unwrap();
+            let accumulator = Accumulator::new(Arc::clone(&quantized), &state);
+            let actual = accumulator.evaluate(state.side_to_move()) as f32;
+            assert!(
+                (actual - expected).abs() < 10.0,
+                "{fen}: {actual} {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_training_reduces_loss() {
+        let records = [
+            (0, 1, "0.5"),
+            (1, 300, "1-0"),
+            (2, -200, "0-1"),
+            (3, 50, "0.5"),
+        ];
+        for optimizer in [Optimizer::Adam, Optimizer::Sgd] {
+            let mut samples: Vec<Sample> = records
+                .iter()
+                .map(|&(fen, score, result)| {
+                    parse_record(&format!("{} | {score} | {result}", FENS[fen]), 0.5).unwrap()
+                })
+                .collect();
+            let mut rng = Rng(3);
+            let mut network = FloatNetwork::random(8, &mut rng);
+            let mut state = OptimizerState::new(optimizer, 0.01, network.params.len());
+            let first = train_epoch(&mut network, &mut state, &mut samples, 2, &mut rng);
+            let mut last = first;
+            for _ in 0..50 {
+                last = train_epoch(&mut network, &mut state, &mut samples, 2, &mut rng);
+            }
+            assert!(last < first / 2.0, "{optimizer:?}: {first} -> {last}");
+        }
+    }
+}
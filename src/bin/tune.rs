@@ -0,0 +1,523 @@
+// Texel tuning of the hand-crafted evaluation.
+//
+//     tune <positions> [--method local|gradient] [--iterations N] [--lr RATE]
+//          [--k K] [--threads N] [--output PATH]
+//
+// Every line of the positions file is a quiet position and the result of the
+// game it was taken from, from White's point of view. The result may follow
+// the FEN as an EPD opcode (`c9 "1-0";`), in brackets (`[0.5]`), after a bar
+// (`| 1/2-1/2`) or as the last word, where it cannot be a bare `1` or `0`.
+// The tuner minimizes the mean squared difference between the results and
+// sigmoid(K * eval / 400), first fitting K to the current weights and then
+// adjusting every weight, either by local search (one step up or down at a
+// time, kept when the error drops) or by gradient descent with Adam on
+// finite-difference gradients. Whenever the error improves, the weights are
+// written to `src/eval/tables.rs`, or to the `--output` path, as Rust source.
+
+use itertools::Itertools;
+use pato::board::state::{Color, PieceType, State};
+use pato::eval::weights::{Field, Weights, S};
+use pato::eval::{evaluate_with, WEIGHTS};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::Instant;
+use strum::IntoEnumIterator;
+
+const USAGE: &str = "usage: tune <positions> [--method local|gradient] [--iterations N] \
+                     [--lr RATE] [--k K] [--threads N] [--output PATH]";
+
+const HEADER: &str = "\
+// The evaluation weights. `tune` rewrites this file with tuned values. The
+// starting material and piece-square values are Ronald Friederich's PeSTO
+// tables; the other terms started from hand-picked values.
+
+use crate::eval::weights::{Weights, S};
+
+#[rustfmt::skip]
+pub const WEIGHTS: Weights = Weights {
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Method {
+    LocalSearch,
+    Gradient,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    positions: String,
+    method: Method,
+    iterations: usize,
+    learning_rate: f64,
+    k: Option<f64>,
+    threads: usize,
+    output: String,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let [positions, flags @ ..] = args else {
+        return Err(USAGE.to_string());
+    };
+    let mut options = Options {
+        positions: positions.clone(),
+        method: Method::LocalSearch,
+        iterations: 100,
+        learning_rate: 1.0,
+        k: None,
+        threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        output: "src/eval/tables.rs".to_string(),
+    };
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("missing value for {flag}"))?;
+        let invalid = || format!("invalid value '{value}' for {flag}");
+        match flag.as_str() {
+            "--iterations" => options.iterations = value.parse().map_err(|_| invalid())?,
+            "--lr" => options.learning_rate = value.parse().map_err(|_| invalid())?,
+            "--k" => options.k = Some(value.parse().map_err(|_| invalid())?),
+            "--threads" => {
+                options.threads = value
+                    .parse()
+                    .ok()
+                    .filter(|&threads| threads > 0)
+                    .ok_or_else(invalid)?
+            }
+            "--output" => options.output = value.clone(),
+            "--method" => {
+                options.method = match value.as_str() {
+                    "local" => Method::LocalSearch,
+                    "gradient" => Method::Gradient,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(format!("unknown option {flag}\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+/// A position and the result of its game for White: 1, 0.5 or 0.
+struct Position {
+    state: State,
+    result: f64,
+}
+
+fn parse_line(line: &str) -> Result<Position, String> {
+    // A bare number ending a line is more likely a FEN's move number than a
+    // result, so only the bracket, quote and bar forms take numbers.
+    let mut numeric = true;
+    let (fen, result) = if let Some(start) = line.find('[') {
+        let end = line[start..].find(']').ok_or("unterminated '['")?;
+        (&line[..start], &line[start + 1..start + end])
+    } else if let Some(start) = line.find('"') {
+        let end = line[start + 1..].find('"').ok_or("unterminated '\"'")?;
+        (&line[..start], &line[start + 1..start + 1 + end])
+    } else if let Some((fen, result)) = line.rsplit_once('|') {
+        // The trainer's `<fen> | <score> | <result>` has a score in between.
+        (fen.split('|').next().unwrap_or(fen), result)
+    } else {
+        numeric = false;
+        line.trim_end()
+            .rsplit_once(char::is_whitespace)
+            .ok_or("missing result")?
+    };
+    let result = match result.trim() {
+        "1-0" | "1.0" => 1.0,
+        "0-1" | "0.0" => 0.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        "1" if numeric => 1.0,
+        "0" if numeric => 0.0,
+        "1/2" if numeric => 0.5,
+        text => return Err(format!("invalid result '{text}'")),
+    };
+
+    // EPD records have four fields and then opcodes such as `c9`; FENs may
+    // add the two move clocks.
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let clocks = fields.len() >= 6
+        && fields[4..6]
+            .iter()
+            .all(|field| field.parse::<u32>().is_ok());
+    let fen = fields[..if clocks { 6 } else { fields.len().min(4) }].join(" ");
+    let state = State::from_fen(&fen).map_err(|error| format!("invalid FEN: {error}"))?;
+    Ok(Position { state, result })
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+struct Tuner {
+    positions: Vec<Position>,
+    k: f64,
+    threads: usize,
+}
+
+impl Tuner {
+    /// The mean squared difference between the results and the win
+    /// probabilities the evaluation with `weights` predicts.
+    fn error(&self, weights: &Weights) -> f64 {
+        let chunk_size = self.positions.len().div_ceil(self.threads).max(1);
+        let total: f64 = thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .positions
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|position| {
+                                let eval = evaluate_with(&position.state, weights);
+                                let white = match position.state.side_to_move() {
+                                    Color::White => eval,
+                                    Color::Black => -eval,
+                                };
+                                let error =
+                                    position.result - sigmoid(self.k * white as f64 / 400.0);
+                                error * error
+                            })
+                            .sum::<f64>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .sum()
+        });
+        total / self.positions.len() as f64
+    }
+
+    fn error_of(&self, params: &[i32]) -> f64 {
+        let mut weights = WEIGHTS.clone();
+        weights.set_params(params);
+        self.error(&weights)
+    }
+
+    /// Sets K to the value that minimizes the error of `weights`, to a
+    /// hundredth.
+    fn fit_k(&mut self, weights: &Weights) -> f64 {
+        let mut best = self.error(weights);
+        for step in [1.0, 0.1, 0.01] {
+            for direction in [1.0, -1.0] {
+                loop {
+                    let previous = self.k;
+                    self.k += direction * step;
+                    let error = self.error(weights);
+                    if self.k > 0.0 && error < best {
+                        best = error;
+                    } else {
+                        self.k = previous;
+                        break;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Tries each weight one higher and one lower, keeping the change when
+    /// the error drops, until a pass changes nothing or `passes` are done.
+    /// `improved` is called after every pass that lowered the error.
+    fn local_search(
+        &self,
+        params: &mut [i32],
+        passes: usize,
+        mut improved: impl FnMut(usize, &[i32], f64),
+    ) -> f64 {
+        let mut best = self.error_of(params);
+        for pass in 1..=passes {
+            let start = best;
+            for index in 0..params.len() {
+                for step in [1, -2] {
+                    params[index] += step;
+                    let error = self.error_of(params);
+                    if error < best {
+                        best = error;
+                        break;
+                    }
+                    if step == -2 {
+                        params[index] += 1;
+                    }
+                }
+            }
+            if best >= start {
+                break;
+            }
+            improved(pass, params, best);
+        }
+        best
+    }
+
+    /// Gradient descent with Adam, the gradient taken by central differences
+    /// of one on every weight. The weights are kept as real numbers and
+    /// rounded for evaluation. `improved` is called after every iteration
+    /// that reached a new lowest error, and `params` ends up with the weights
+    /// that did best.
+    fn gradient_descent(
+        &self,
+        params: &mut [i32],
+        iterations: usize,
+        learning_rate: f64,
+        mut improved: impl FnMut(usize, &[i32], f64),
+    ) -> f64 {
+        const BETA1: f64 = 0.9;
+        const BETA2: f64 = 0.999;
+        const EPSILON: f64 = 1e-12;
+
+        let mut values: Vec<f64> = params.iter().map(|&param| param as f64).collect();
+        let mut first = vec![0.0; params.len()];
+        let mut second = vec![0.0; params.len()];
+        let mut current = params.to_vec();
+        let mut best = self.error_of(params);
+        for iteration in 1..=iterations {
+            let mut gradient = vec![0.0; params.len()];
+            for (index, slope) in gradient.iter_mut().enumerate() {
+                current[index] += 1;
+                let above = self.error_of(&current);
+                current[index] -= 2;
+                let below = self.error_of(&current);
+                current[index] += 1;
+                *slope = (above - below) / 2.0;
+            }
+
+            let correction1 = 1.0 - BETA1.powi(iteration as i32);
+            let correction2 = 1.0 - BETA2.powi(iteration as i32);
+            for index in 0..params.len() {
+                let slope = gradient[index];
+                first[index] = BETA1 * first[index] + (1.0 - BETA1) * slope;
+                second[index] = BETA2 * second[index] + (1.0 - BETA2) * slope * slope;
+                values[index] -= learning_rate * (first[index] / correction1)
+                    / ((second[index] / correction2).sqrt() + EPSILON);
+                current[index] = values[index].round() as i32;
+            }
+
+            let error = self.error_of(&current);
+            if error < best {
+                best = error;
+                params.copy_from_slice(&current);
+                improved(iteration, params, best);
+            }
+        }
+        best
+    }
+}
+
+/// `weights` as the source of `src/eval/tables.rs`.
+fn to_source(weights: &Weights) -> String {
+    fn rows(source: &mut String, values: &[S], indent: &str) {
+        for row in values.chunks(8) {
+            let row = row
+                .iter()
+                .map(|value| format!("S({}, {})", value.0, value.1));
+            writeln!(source, "{indent}{},", row.format(", ")).unwrap();
+        }
+    }
+
+    let mut source = HEADER.to_string();
+    weights.clone().for_each_field(|name, field| match field {
+        Field::Scalar(value) => {
+            writeln!(source, "    {name}: S({}, {}),", value.0, value.1).unwrap();
+        }
+        Field::Ints(values) => {
+            writeln!(source, "    {name}: [{}],", values.iter().format(", ")).unwrap();
+        }
+        Field::Row(values) if values.len() <= 4 => {
+            let values = values
+                .iter()
+                .map(|value| format!("S({}, {})", value.0, value.1));
+            writeln!(source, "    {name}: [{}],", values.format(", ")).unwrap();
+        }
+        Field::Row(values) => {
+            writeln!(source, "    {name}: [").unwrap();
+            rows(&mut source, values, "        ");
+            source.push_str("    ],\n");
+        }
+        Field::Tables(tables) => {
+            writeln!(source, "    {name}: [").unwrap();
+            for (piece, table) in PieceType::iter().zip(tables.iter()) {
+                writeln!(source, "        // {piece:?}\n        [").unwrap();
+                rows(&mut source, table, "            ");
+                source.push_str("        ],\n");
+            }
+            source.push_str("    ],\n");
+        }
+    });
+    source.push_str("};\n");
+    source
+}
+
+fn load_positions(path: &str) -> Vec<Position> {
+    let text = fs::read_to_string(path).unwrap_or_else(|error| {
+        eprintln!("cannot read {path}: {error}");
+        process::exit(1);
+    });
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(position) => positions.push(position),
+            Err(error) => {
+                if skipped < 10 {
+                    eprintln!("{path}:{}: {error}", number + 1);
+                }
+                skipped += 1;
+            }
+        }
+    }
+    if skipped > 0 {
+        eprintln!("skipped {skipped} invalid lines");
+    }
+    positions
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        process::exit(2);
+    });
+
+    let positions = load_positions(&options.positions);
+    if positions.is_empty() {
+        eprintln!("no positions to tune on");
+        process::exit(1);
+    }
+    println!("tuning on {} positions", positions.len());
+
+    let mut tuner = Tuner {
+        positions,
+        k: options.k.unwrap_or(1.0),
+        threads: options.threads,
+    };
+    let initial = match options.k {
+        Some(_) => tuner.error(&WEIGHTS),
+        None => tuner.fit_k(&WEIGHTS),
+    };
+    println!("K = {:.2}, error {initial:.6}", tuner.k);
+
+    let start = Instant::now();
+    let save = |step: usize, params: &[i32], error: f64| {
+        let mut weights = WEIGHTS.clone();
+        weights.set_params(params);
+        if let Err(error) = fs::write(&options.output, to_source(&weights)) {
+            eprintln!("cannot write {}: {error}", options.output);
+            process::exit(1);
+        }
+        println!(
+            "{step}: error {error:.6}, {:.0}s",
+            start.elapsed().as_secs_f64()
+        );
+    };
+    let mut params = WEIGHTS.to_params();
+    let error = match options.method {
+        Method::LocalSearch => tuner.local_search(&mut params, options.iterations, save),
+        Method::Gradient => {
+            tuner.gradient_descent(&mut params, options.iterations, options.learning_rate, save)
+        }
+    };
+    println!("done: error {error:.6}, weights in {}", options.output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner(lines: &[&str]) -> Tuner {
+        Tuner {
+            positions: lines.iter().map(|line| parse_line(line).unwrap()).collect(),
+            k: 1.0,
+            threads: 2,
+        }
+    }
+
+    // Positions whose results disagree with the evaluation: White is a pawn
+    // up in the first two and loses.
+    const LINES: [&str; 4] = [
+        "4k3/pp6/8/8/8/8/PPP5/4K3 w - - c9 \"0-1\";",
+        "4k3/pp6/8/8/8/8/PPP5/4K3 b - - [0.0]",
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 | 35 | 1/2-1/2",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 1-0",
+    ];
+
+    #[test]
+    fn test_source_reproduces_tables() {
+        assert_eq!(to_source(&WEIGHTS), include_str!("../eval/tables.rs"));
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let results: Vec<f64> = LINES
+            .iter()
+            .map(|line| parse_line(line).unwrap().result)
+            .collect();
+        assert_eq!(results, [0.0, 0.0, 0.5, 1.0]);
+        let position = parse_line(LINES[2]).unwrap();
+        assert_eq!(position.state.side_to_move(), Color::Black);
+        assert_eq!(position.state.fullmove_number(), 1);
+
+        assert!(parse_line("4k3/8/8/8/8/8/8/4K3 w - - [2]").is_err());
+        assert!(parse_line("4k3/8/8/8/8/8/8/4K3 w - - \"1-0").is_err());
+        assert!(parse_line("4k3/8/8/8/8/8 w - - 1-0").is_err());
+        // Without a result, the move number is not taken for one.
+        assert!(parse_line("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").is_err());
+        assert_eq!(
+            parse_line("4k3/8/8/8/8/8/4P3/4K3 w - - [1]")
+                .unwrap()
+                .result,
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args: Vec<String> = ["quiet.epd", "--method", "gradient", "--k", "1.5"]
+            .map(String::from)
+            .to_vec();
+        let options = parse_options(&args).unwrap();
+        assert_eq!(options.method, Method::Gradient);
+        assert_eq!(options.k, Some(1.5));
+        assert_eq!(options.output, "src/eval/tables.rs");
+        assert!(parse_options(&[]).is_err());
+        assert!(parse_options(&args[..2]).is_err());
+    }
+
+    #[test]
+    fn test_fit_k() {
+        let mut tuner = tuner(&LINES[2..]);
+        let error = tuner.fit_k(&WEIGHTS);
+        assert!(tuner.k > 0.0);
+        assert!(error <= tuner.error(&WEIGHTS) + 1e-12);
+    }
+
+    #[test]
+    fn test_local_search_lowers_error() {
+        let tuner = tuner(&LINES);
+        let mut params = WEIGHTS.to_params();
+        let initial = tuner.error_of(&params);
+        let mut passes = 0;
+        let error = tuner.local_search(&mut params, 1, |_, _, _| passes += 1);
+        assert!(error < initial);
+        assert_eq!(passes, 1);
+        assert_eq!(tuner.error_of(&params), error);
+    }
+
+    #[test]
+    fn test_gradient_descent_lowers_error() {
+        let tuner = tuner(&LINES);
+        let mut params = WEIGHTS.to_params();
+        let initial = tuner.error_of(&params);
+        let error = tuner.gradient_descent(&mut params, 2, 2.0, |_, _, _| {});
+        assert!(error < initial);
+        assert_eq!(tuner.error_of(&params), error);
+    }
+}
@@ -1,3 +1,9 @@
-mod bitboard;
+pub mod attacks;
+pub mod bitboard;
 pub mod display;
+mod magic;
+pub mod movegen;
+pub mod moves;
+pub mod perft;
+pub mod see;
 pub mod state;
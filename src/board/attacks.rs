@@ -0,0 +1,106 @@
+use crate::board::bitboard::{attacked_squares_for, Bitboard};
+use crate::board::state::{Color, PieceType, Square, State};
+use strum::{EnumCount, IntoEnumIterator};
+
+/// The squares each piece in a position attacks, and each side by piece
+/// type. Sliders are blocked by pieces of either color, and pinned pieces
+/// still attack. Computed once per evaluation, so that the terms reading
+/// them do not generate attacks again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttackMaps {
+    by_square: [Bitboard; 64],
+    by_piece: [[Bitboard; PieceType::COUNT]; Color::COUNT],
+}
+
+impl AttackMaps {
+    /// The squares attacked by the piece on `square`, none if it is empty.
+    pub fn from_square(&self, square: Square) -> Bitboard {
+        self.by_square[square as usize]
+    }
+
+    /// The squares attacked by at least one `color` `piece`.
+    pub fn by_piece(&self, piece: PieceType, color: Color) -> Bitboard {
+        self.by_piece[color as usize][piece as usize]
+    }
+}
+
+impl State {
+    /// Computes the attack maps of both sides.
+    pub fn attack_maps(&self) -> AttackMaps {
+        let occupied = self.occupied();
+        let mut maps = AttackMaps {
+            by_square: [Bitboard::EMPTY; 64],
+            by_piece: [[Bitboard::EMPTY; PieceType::COUNT]; Color::COUNT],
+        };
+        for color in Color::iter() {
+            for piece in PieceType::iter() {
+                for square in self.get_bitboard_for(piece, color).squares() {
+                    let attacks = attacked_squares_for(piece, color, square, occupied);
+                    maps.by_square[square as usize] = attacks;
+                    maps.by_piece[color as usize][piece as usize] |= attacks;
+                }
+            }
+        }
+        maps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The squares attacked by at least one `color` piece.
+    fn by_color(maps: &AttackMaps, color: Color) -> Bitboard {
+        PieceType::iter().fold(Bitboard::EMPTY, |attacks, piece| {
+            attacks | maps.by_piece(piece, color)
+        })
+    }
+
+    #[test]
+    fn test_start_position() {
+        let maps = State::new().attack_maps();
+        // Every square of the third rank, and the first two ranks but the
+        // corners.
+        assert_eq!(by_color(&maps, Color::White).count(), 22);
+        assert_eq!(by_color(&maps, Color::Black).count(), 22);
+        assert!(maps
+            .by_piece(PieceType::Pawn, Color::White)
+            .square_occupied(Square::A3));
+        assert!(!maps
+            .by_piece(PieceType::Pawn, Color::White)
+            .square_occupied(Square::A2));
+        assert!(maps
+            .by_piece(PieceType::Knight, Color::Black)
+            .square_occupied(Square::F6));
+        assert!(maps
+            .by_piece(PieceType::Queen, Color::White)
+            .square_occupied(Square::D2));
+        assert!(!maps
+            .by_piece(PieceType::Queen, Color::White)
+            .square_occupied(Square::D3));
+        assert_eq!(maps.from_square(Square::B1).count(), 3);
+        assert_eq!(maps.from_square(Square::E2).count(), 2);
+        assert!(maps.from_square(Square::E4).is_empty());
+    }
+
+    #[test]
+    fn test_maps_match_attackers() {
+        let state =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let maps = state.attack_maps();
+        for index in 0..64u8 {
+            let square = Square::from_file_rank(index / 8, index % 8).unwrap();
+            for color in Color::iter() {
+                let attacked = !(state.attackers_to(square, state.occupied())
+                    & state.occupied_by(color))
+                .is_empty();
+                assert_eq!(
+                    by_color(&maps, color).square_occupied(square),
+                    attacked,
+                    "{square:?}"
+                );
+            }
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use crate::board::magic::{bishop_attacks, rook_attacks};
 use crate::board::state::Square::{
     A1, A2, A7, A8, B1, B2, B7, B8, C1, C2, C7, C8, D1, D2, D7, D8, E1, E2, E7, E8, F1, F2, F7, F8,
     G1, G2, G7, G8, H1, H2, H7, H8,
 };
 use crate::board::state::*;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+use std::sync::LazyLock;
+use strum::IntoEnumIterator;
 
 pub type Bits = u64;
 pub type Index = u8;
@@ -21,11 +25,13 @@ pub const Q_B_INIT: [Square; 1] = [D8];
 pub const K_W_INIT: [Square; 1] = [E1];
 pub const K_B_INIT: [Square; 1] = [E8];
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
 pub struct Bitboard {
     data: Bits,
 }
 impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard { data: 0 };
+
     pub fn from_squares(squares: &[Square]) -> Self {
         let mut data: Bits = 0;
         for square in squares {
@@ -34,7 +40,7 @@ impl Bitboard {
         Bitboard { data }
     }
 
-    pub fn from_bits(bits: Bits) -> Self {
+    pub const fn from_bits(bits: Bits) -> Self {
         Bitboard { data: bits }
     }
 
@@ -45,7 +51,7 @@ impl Bitboard {
     pub fn squares_occupied(&self, squares: &[Square]) -> bool {
         for square in squares {
             if !self.square_occupied(*square) {
-                false;
+                return false;
             }
         }
         true
@@ -55,20 +61,105 @@ impl Bitboard {
         self.data & Self::bits_from_square(square) != 0
     }
 
+    pub fn set_square(&mut self, square: Square) {
+        self.data |= Self::bits_from_square(square);
+    }
+
+    pub fn clear_square(&mut self, square: Square) {
+        self.data &= !Self::bits_from_square(square);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data == 0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.data.count_ones()
+    }
+
+    /// The occupied squares, from A1 upwards.
+    pub fn squares(&self) -> Squares {
+        Squares { bits: self.data }
+    }
+
     const fn bits_from_square(square: Square) -> Bits {
         1 << square as Index
     }
 }
 
-pub fn attacked_squares_for(piece: PieceType, square: Square) -> Bitboard {
+pub struct Squares {
+    bits: Bits,
+}
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.bits == 0 {
+            return None;
+        }
+        let square = Square::from_repr(self.bits.trailing_zeros() as i8);
+        self.bits &= self.bits - 1;
+        square
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, other: Bitboard) -> Bitboard {
+        Bitboard::from_bits(self.data & other.data)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, other: Bitboard) -> Bitboard {
+        Bitboard::from_bits(self.data | other.data)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard::from_bits(!self.data)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, other: Bitboard) {
+        self.data &= other.data;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, other: Bitboard) {
+        self.data |= other.data;
+    }
+}
+
+/// Squares attacked by a `color` `piece` on `square`. Only pawns depend on the
+/// color. Sliding pieces stop at the first square that is set in `occupied`,
+/// which is included in the result.
+pub fn attacked_squares_for(
+    piece: PieceType,
+    color: Color,
+    square: Square,
+    occupied: Bitboard,
+) -> Bitboard {
     match piece {
-        PieceType::Pawn => attacked_squares_for_pawn(Bitboard::from_squares(&[square]).get_bits()),
-        PieceType::Knight => {
-            attacked_squares_for_knight(Bitboard::from_squares(&[square]).get_bits())
-            // todo create lookup table
+        PieceType::Pawn => {
+            attacked_squares_for_pawn(Bitboard::from_squares(&[square]).get_bits(), color)
         }
-        PieceType::King => attacked_squares_for_king(Bitboard::from_squares(&[square]).get_bits()),
-        _ => Bitboard::from_squares(&[square]), // todo create lookup table
+        PieceType::Knight => Bitboard::from_bits(KNIGHT_ATTACKS[square as usize]),
+        PieceType::King => Bitboard::from_bits(KING_ATTACKS[square as usize]),
+        PieceType::Bishop => Bitboard::from_bits(bishop_attacks(square, occupied.get_bits())),
+        PieceType::Rook => Bitboard::from_bits(rook_attacks(square, occupied.get_bits())),
+        PieceType::Queen => Bitboard::from_bits(
+            bishop_attacks(square, occupied.get_bits()) | rook_attacks(square, occupied.get_bits()),
+        ),
     }
 }
 
@@ -80,61 +171,252 @@ const NOT_RANK_1: Bits = 0xFEFEFEFEFEFEFEFE;
 const NOT_RANK_2: Bits = 0xFDFDFDFDFDFDFDFD;
 const NOT_RANK_7: Bits = 0xBFBFBFBFBFBFBFBF;
 const NOT_RANK_8: Bits = 0x7F7F7F7F7F7F7F7F;
+const RANK_1: Bits = 0x0101010101010101;
+const RANK_3: Bits = 0x0404040404040404;
+const RANK_6: Bits = 0x2020202020202020;
+const RANK_8: Bits = 0x8080808080808080;
+
+fn attacked_squares_for_pawn(pawn: Bits, color: Color) -> Bitboard {
+    match color {
+        Color::White => Bitboard::from_bits(
+            (pawn & NOT_FILE_A & NOT_RANK_8) >> 7 | (pawn & NOT_FILE_H & NOT_RANK_8) << 9,
+        ),
+        Color::Black => Bitboard::from_bits(
+            (pawn & NOT_FILE_A & NOT_RANK_1) >> 9 | (pawn & NOT_FILE_H & NOT_RANK_1) << 7,
+        ),
+    }
+}
+
+/// Squares attacked by any of the `color` `pawns`.
+pub fn pawn_attacks(color: Color, pawns: Bitboard) -> Bitboard {
+    attacked_squares_for_pawn(pawns.get_bits(), color)
+}
+
+/// Squares a `color` pawn on `square` can push to: one step forward onto an
+/// empty square, and a second step when it stands on its initial rank and both
+/// squares are empty.
+pub fn pawn_pushes(color: Color, square: Square, occupied: Bitboard) -> Bitboard {
+    let pawn = Bitboard::from_squares(&[square]).get_bits();
+    let empty = !occupied.get_bits();
+    let pushes = match color {
+        Color::White => {
+            let single = (pawn & NOT_RANK_8) << 1 & empty;
+            single | (single & RANK_3) << 1 & empty
+        }
+        Color::Black => {
+            let single = (pawn & NOT_RANK_1) >> 1 & empty;
+            single | (single & RANK_6) >> 1 & empty
+        }
+    };
+    Bitboard::from_bits(pushes)
+}
+
+/// The rank on which a `color` pawn promotes.
+pub fn promotion_rank(color: Color) -> Bitboard {
+    match color {
+        Color::White => Bitboard::from_bits(RANK_8),
+        Color::Black => Bitboard::from_bits(RANK_1),
+    }
+}
+
+/// The en passant target left behind by a `color` pawn moving from `from` to
+/// `to`: the square it skipped when the move is a double push.
+pub fn en_passant_target(color: Color, from: Square, to: Square) -> Option<Square> {
+    let (start_rank, skipped_rank, target_rank) = match color {
+        Color::White => (1, 2, 3),
+        Color::Black => (6, 5, 4),
+    };
+    if from.file() != to.file() || from.rank() != start_rank || to.rank() != target_rank {
+        return None;
+    }
+    Square::from_file_rank(from.file(), skipped_rank)
+}
 
-fn attacked_squares_for_pawn(pawn: Bits) -> Bitboard {
-    Bitboard::from_bits((pawn & NOT_FILE_A) >> 7 | (pawn & NOT_FILE_H) << 9)
+const fn attacked_squares_for_knight(knight: Bits) -> Bits {
+    (knight & NOT_FILE_G & NOT_FILE_H & NOT_RANK_8) << 17
+        | (knight & NOT_FILE_H & NOT_RANK_7 & NOT_RANK_8) << 10
+        | (knight & NOT_FILE_A & NOT_RANK_7 & NOT_RANK_8) >> 6
+        | (knight & NOT_FILE_A & NOT_FILE_B & NOT_RANK_8) >> 15
+        | (knight & NOT_FILE_A & NOT_FILE_B & NOT_RANK_1) >> 17
+        | (knight & NOT_FILE_A & NOT_RANK_1 & NOT_RANK_2) >> 10
+        | (knight & NOT_FILE_H & NOT_RANK_1 & NOT_RANK_2) << 6
+        | (knight & NOT_FILE_G & NOT_FILE_H & NOT_RANK_1) << 15
 }
 
-fn attacked_squares_for_knight(knight: Bits) -> Bitboard {
-    Bitboard::from_bits(
-        (knight & NOT_FILE_G & NOT_FILE_H & NOT_RANK_8) << 17
-            | (knight & NOT_FILE_H & NOT_RANK_7 & NOT_RANK_8) << 10
-            | (knight & NOT_FILE_A & NOT_RANK_7 & NOT_RANK_8) >> 6
-            | (knight & NOT_FILE_A & NOT_FILE_B & NOT_RANK_8) >> 15
-            | (knight & NOT_FILE_A & NOT_FILE_B & NOT_RANK_1) >> 17
-            | (knight & NOT_FILE_A & NOT_RANK_1 & NOT_RANK_2) >> 10
-            | (knight & NOT_FILE_H & NOT_RANK_1 & NOT_RANK_2) << 6
-            | (knight & NOT_FILE_G & NOT_FILE_H & NOT_RANK_1) << 15,
-    )
+const fn attacked_squares_for_king(king: Bits) -> Bits {
+    (king & NOT_RANK_8) << 1
+        | (king & NOT_FILE_H & NOT_RANK_8) << 9
+        | (king & NOT_FILE_H) << 8
+        | (king & NOT_FILE_H & NOT_RANK_1) << 7
+        | (king & NOT_RANK_1) >> 1
+        | (king & NOT_FILE_A & NOT_RANK_1) >> 9
+        | (king & NOT_FILE_A) >> 8
+        | (king & NOT_FILE_A & NOT_RANK_8) >> 7
 }
 
-fn attacked_squares_for_king(king: Bits) -> Bitboard {
-    Bitboard::from_bits(
-        (king & NOT_RANK_8) << 1
-            | (king & NOT_FILE_H & NOT_RANK_8) << 9
-            | (king & NOT_FILE_H) << 8
-            | (king & NOT_FILE_H & NOT_RANK_1) << 7
-            | (king & NOT_RANK_1) >> 1
-            | (king & NOT_FILE_A & NOT_RANK_1) >> 9
-            | (king & NOT_FILE_A) >> 8
-            | (king & NOT_FILE_A & NOT_RANK_8) >> 7,
-    )
+// Lookup tables indexed by square, evaluated at compile time.
+const KNIGHT_ATTACKS: [Bits; 64] = {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        table[index] = attacked_squares_for_knight(1 << index);
+        index += 1;
+    }
+    table
+};
+
+const KING_ATTACKS: [Bits; 64] = {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        table[index] = attacked_squares_for_king(1 << index);
+        index += 1;
+    }
+    table
+};
+
+/// The squares strictly between `a` and `b` when they share a rank, file or
+/// diagonal, and no squares otherwise.
+pub fn squares_between(a: Square, b: Square) -> Bitboard {
+    Bitboard::from_bits(LINES.between[a as usize * 64 + b as usize])
 }
 
+/// The whole rank, file or diagonal through `a` and `b`, edge to edge, or no
+/// squares when they are not aligned.
+pub fn line_through(a: Square, b: Square) -> Bitboard {
+    Bitboard::from_bits(LINES.line[a as usize * 64 + b as usize])
+}
+
+struct LineTables {
+    between: Vec<Bits>,
+    line: Vec<Bits>,
+}
+
+static LINES: LazyLock<LineTables> = LazyLock::new(|| {
+    let mut tables = LineTables {
+        between: vec![0; 64 * 64],
+        line: vec![0; 64 * 64],
+    };
+    for a in Square::iter() {
+        for b in Square::iter() {
+            let (a_bits, b_bits) = (1 << a as Index, 1 << b as Index);
+            for (empty_board_attacks, attacks) in [
+                (rook_attacks(a, 0), rook_attacks as fn(Square, Bits) -> Bits),
+                (bishop_attacks(a, 0), bishop_attacks),
+            ] {
+                if a != b && empty_board_attacks & b_bits != 0 {
+                    let index = a as usize * 64 + b as usize;
+                    tables.between[index] = attacks(a, b_bits) & attacks(b, a_bits);
+                    tables.line[index] = attacks(a, 0) & attacks(b, 0) | a_bits | b_bits;
+                }
+            }
+        }
+    }
+    tables
+});
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::board::display::print_bitboard;
-    use crate::board::state::PieceType::{King, Knight, Pawn};
+    use crate::board::state::Color::{Black, White};
+    use crate::board::state::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
     use crate::board::state::Square::*;
 
+    const EMPTY: Bitboard = Bitboard::EMPTY;
+
+    #[test]
+    fn test_bitboard_operations() {
+        let check = Bitboard::from_squares;
+        let mut bitboard = check(&[A1, E4, H8]);
+        assert_eq!(bitboard.count(), 3);
+        assert_eq!(bitboard.squares().collect::<Vec<_>>(), vec![A1, E4, H8]);
+        assert_eq!(bitboard & check(&[E4, D4]), check(&[E4]));
+        assert_eq!(bitboard | check(&[D4]), check(&[A1, D4, E4, H8]));
+        assert_eq!((!bitboard).count(), 61);
+        bitboard.clear_square(E4);
+        assert_eq!(bitboard, check(&[A1, H8]));
+        bitboard &= check(&[H8]);
+        assert_eq!(bitboard, check(&[H8]));
+        bitboard |= check(&[B2]);
+        assert_eq!(bitboard, check(&[B2, H8]));
+        assert!(EMPTY.is_empty() && EMPTY.squares().next().is_none());
+    }
+
     #[test]
     fn test_attacked_squares_for_pawn() {
         let from_squares = Bitboard::from_squares;
-        assert_eq!(attacked_squares_for(Pawn, A2), from_squares(&[B3]));
-        assert_eq!(attacked_squares_for(Pawn, B2), from_squares(&[A3, C3]));
-        assert_eq!(attacked_squares_for(Pawn, C2), from_squares(&[B3, D3]));
-        assert_eq!(attacked_squares_for(Pawn, D2), from_squares(&[C3, E3]));
-        assert_eq!(attacked_squares_for(Pawn, E2), from_squares(&[D3, F3]));
-        assert_eq!(attacked_squares_for(Pawn, F2), from_squares(&[E3, G3]));
-        assert_eq!(attacked_squares_for(Pawn, G2), from_squares(&[F3, H3]));
-        assert_eq!(attacked_squares_for(Pawn, H2), from_squares(&[G3]));
+        let attacked_squares_for = |color, square| attacked_squares_for(Pawn, color, square, EMPTY);
+
+        // White
+        assert_eq!(attacked_squares_for(White, A2), from_squares(&[B3]));
+        assert_eq!(attacked_squares_for(White, B2), from_squares(&[A3, C3]));
+        assert_eq!(attacked_squares_for(White, C2), from_squares(&[B3, D3]));
+        assert_eq!(attacked_squares_for(White, D2), from_squares(&[C3, E3]));
+        assert_eq!(attacked_squares_for(White, E2), from_squares(&[D3, F3]));
+        assert_eq!(attacked_squares_for(White, F2), from_squares(&[E3, G3]));
+        assert_eq!(attacked_squares_for(White, G2), from_squares(&[F3, H3]));
+        assert_eq!(attacked_squares_for(White, H2), from_squares(&[G3]));
+        assert_eq!(attacked_squares_for(White, D7), from_squares(&[C8, E8]));
+        assert_eq!(attacked_squares_for(White, H8), from_squares(&[]));
+
+        // Black
+        assert_eq!(attacked_squares_for(Black, A7), from_squares(&[B6]));
+        assert_eq!(attacked_squares_for(Black, B7), from_squares(&[A6, C6]));
+        assert_eq!(attacked_squares_for(Black, C7), from_squares(&[B6, D6]));
+        assert_eq!(attacked_squares_for(Black, D7), from_squares(&[C6, E6]));
+        assert_eq!(attacked_squares_for(Black, E7), from_squares(&[D6, F6]));
+        assert_eq!(attacked_squares_for(Black, F7), from_squares(&[E6, G6]));
+        assert_eq!(attacked_squares_for(Black, G7), from_squares(&[F6, H6]));
+        assert_eq!(attacked_squares_for(Black, H7), from_squares(&[G6]));
+        assert_eq!(attacked_squares_for(Black, D2), from_squares(&[C1, E1]));
+        assert_eq!(attacked_squares_for(Black, A1), from_squares(&[]));
+    }
+
+    #[test]
+    fn test_pawn_pushes() {
+        let check = Bitboard::from_squares;
+
+        // White
+        assert_eq!(pawn_pushes(White, E2, EMPTY), check(&[E3, E4]));
+        assert_eq!(pawn_pushes(White, E2, check(&[E4])), check(&[E3]));
+        assert_eq!(pawn_pushes(White, E2, check(&[E3])), check(&[]));
+        assert_eq!(pawn_pushes(White, E3, EMPTY), check(&[E4]));
+        assert_eq!(pawn_pushes(White, A7, EMPTY), check(&[A8]));
+        assert_eq!(pawn_pushes(White, H8, EMPTY), check(&[]));
+
+        // Black
+        assert_eq!(pawn_pushes(Black, D7, EMPTY), check(&[D6, D5]));
+        assert_eq!(pawn_pushes(Black, D7, check(&[D5])), check(&[D6]));
+        assert_eq!(pawn_pushes(Black, D7, check(&[D6])), check(&[]));
+        assert_eq!(pawn_pushes(Black, D6, EMPTY), check(&[D5]));
+        assert_eq!(pawn_pushes(Black, H2, EMPTY), check(&[H1]));
+        assert_eq!(pawn_pushes(Black, A1, EMPTY), check(&[]));
+    }
+
+    #[test]
+    fn test_promotion_rank() {
+        assert!(promotion_rank(White).squares_occupied(&[A8, B8, C8, D8, E8, F8, G8, H8]));
+        assert!(!promotion_rank(White).square_occupied(A1));
+        assert!(promotion_rank(Black).squares_occupied(&[A1, B1, C1, D1, E1, F1, G1, H1]));
+        assert!(!promotion_rank(Black).square_occupied(H8));
+    }
+
+    #[test]
+    fn test_en_passant_target() {
+        assert_eq!(en_passant_target(White, E2, E4), Some(E3));
+        assert_eq!(en_passant_target(White, A2, A4), Some(A3));
+        assert_eq!(en_passant_target(White, E2, E3), None);
+        assert_eq!(en_passant_target(White, E3, E5), None);
+        assert_eq!(en_passant_target(Black, D7, D5), Some(D6));
+        assert_eq!(en_passant_target(Black, H7, H5), Some(H6));
+        assert_eq!(en_passant_target(Black, D7, D6), None);
+        assert_eq!(en_passant_target(Black, D2, D4), None);
     }
 
     #[test]
     fn test_attacked_squares_knight() {
         let check = Bitboard::from_squares;
-        let test = attacked_squares_for;
+        let test = |piece, square| attacked_squares_for(piece, White, square, EMPTY);
 
         // A-file
         assert_eq!(test(Knight, A1), check(&[B3, C2]));
@@ -220,7 +502,7 @@ mod tests {
     #[test]
     fn test_attacked_squares_king() {
         let check = Bitboard::from_squares;
-        let test = attacked_squares_for;
+        let test = |piece, square| attacked_squares_for(piece, White, square, EMPTY);
 
         // A-file
         assert_eq!(test(King, A1), check(&[A2, B1, B2]));
@@ -302,4 +584,81 @@ mod tests {
         assert_eq!(test(King, H7), check(&[G6, G7, G8, H6, H8]));
         assert_eq!(test(King, H8), check(&[G7, G8, H7]));
     }
+
+    #[test]
+    fn test_attacked_squares_bishop() {
+        let check = Bitboard::from_squares;
+        let test = |piece, square, occupied| attacked_squares_for(piece, White, square, occupied);
+
+        assert_eq!(
+            test(Bishop, A1, EMPTY),
+            check(&[B2, C3, D4, E5, F6, G7, H8])
+        );
+        assert_eq!(
+            test(Bishop, D4, EMPTY),
+            check(&[A1, B2, C3, E5, F6, G7, H8, A7, B6, C5, E3, F2, G1])
+        );
+        assert_eq!(
+            test(Bishop, D4, check(&[B2, F6, C5, F2])),
+            check(&[B2, C3, E5, F6, C5, E3, F2])
+        );
+        assert_eq!(test(Bishop, H8, check(&[G7])), check(&[G7]));
+    }
+
+    #[test]
+    fn test_attacked_squares_rook() {
+        let check = Bitboard::from_squares;
+        let test = |piece, square, occupied| attacked_squares_for(piece, White, square, occupied);
+
+        assert_eq!(
+            test(Rook, A1, EMPTY),
+            check(&[A2, A3, A4, A5, A6, A7, A8, B1, C1, D1, E1, F1, G1, H1])
+        );
+        assert_eq!(
+            test(Rook, E4, check(&[E2, E7, B4, G4, A1, H8])),
+            check(&[E2, E3, E5, E6, E7, B4, C4, D4, F4, G4])
+        );
+        assert_eq!(test(Rook, H8, check(&[G8, H7])), check(&[G8, H7]));
+    }
+
+    #[test]
+    fn test_squares_between() {
+        let check = Bitboard::from_squares;
+        assert_eq!(squares_between(A1, A4), check(&[A2, A3]));
+        assert_eq!(squares_between(H1, C6), check(&[G2, F3, E4, D5]));
+        assert_eq!(squares_between(E4, B4), check(&[C4, D4]));
+        assert_eq!(squares_between(E4, E5), EMPTY);
+        assert_eq!(squares_between(A1, B3), EMPTY);
+        assert_eq!(squares_between(D4, D4), EMPTY);
+    }
+
+    #[test]
+    fn test_line_through() {
+        let check = Bitboard::from_squares;
+        assert_eq!(
+            line_through(C3, E5),
+            check(&[A1, B2, C3, D4, E5, F6, G7, H8])
+        );
+        assert_eq!(
+            line_through(B4, B6),
+            check(&[B1, B2, B3, B4, B5, B6, B7, B8])
+        );
+        assert_eq!(line_through(H2, G1), check(&[G1, H2]));
+        assert_eq!(line_through(A1, B3), EMPTY);
+    }
+
+    #[test]
+    fn test_attacked_squares_queen() {
+        let check = Bitboard::from_squares;
+        let test = |piece, square, occupied| attacked_squares_for(piece, White, square, occupied);
+
+        assert_eq!(
+            test(Queen, D1, check(&[C1, E1, C2, D2, E2])),
+            check(&[C1, E1, C2, D2, E2])
+        );
+        assert_eq!(
+            test(Queen, B2, check(&[B4, D4, D2])),
+            check(&[A1, A2, A3, B1, B3, B4, C1, C2, C3, D2, D4])
+        );
+    }
 }
@@ -20,7 +20,7 @@ fn print_rank_bb(bitboard: &Bitboard, squares: &[Square]) {
     for square in squares {
         print_square_bb(bitboard, square);
     }
-    print!("\n");
+    println!();
 }
 
 fn print_square_bb(bitboard: &Bitboard, square: &Square) {
@@ -47,7 +47,7 @@ fn print_rank(state: &State, squares: &[Square]) {
     for square in squares {
         print_square(state, square);
     }
-    print!("\n");
+    println!();
 }
 
 fn print_square(state: &State, square: &Square) {
@@ -0,0 +1,304 @@
+use crate::board::bitboard::Bits;
+use crate::board::state::Square;
+use std::sync::LazyLock;
+
+// Magic bitboards: for every square the occupancy of the relevant rays is
+// multiplied by a magic number, and the top bits of the product index a table
+// holding the attack set for that occupancy. The magic numbers below were found
+// by a fixed-seed random search for this crate's square layout; building the
+// tables verifies that none of them maps two different attack sets to the same
+// entry.
+
+pub const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+pub const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x10102002004A1420,
+    0x8020040400584008,
+    0x10510800811201C8,
+    0x5204042080000088,
+    0x2204106880000002,
+    0x1401042004000000,
+    0x0400880410042004,
+    0x0028208200A02020,
+    0x1500241990010E00,
+    0x8001200182020A40,
+    0x40004101030B0000,
+    0x8002041042000100,
+    0x4010011041020038,
+    0x0000010421044000,
+    0x1500210808020A00,
+    0x8000088400880520,
+    0x0405004010040100,
+    0x1005823210040108,
+    0x2708008102040011,
+    0x4048200404009100,
+    0x0018104101400024,
+    0x0003000601190101,
+    0x8004803108491000,
+    0x8014241200820800,
+    0x0006E080100C3040,
+    0x0501044A11041800,
+    0x9020300008004045,
+    0x0894080000220040,
+    0x1001010083104000,
+    0x5004030040900080,
+    0x000400422C012400,
+    0x0002128698404812,
+    0x1010108404900440,
+    0x0928021182084100,
+    0x2006080409020024,
+    0x1010202020180080,
+    0xA010008200202200,
+    0x2098015100019004,
+    0x0002041440810811,
+    0x802A02020000B098,
+    0x0009015090004060,
+    0x4000821082081001,
+    0x0100210040420800,
+    0x0800004010488A00,
+    0x2000081104004040,
+    0x4C8E029015000082,
+    0x0420340322224842,
+    0x1298260043400210,
+    0x0000822802400008,
+    0x00008A0101600000,
+    0x3040003412080021,
+    0x3040290220884800,
+    0x4A1500401041004A,
+    0x8010200282020781,
+    0x0020203142209091,
+    0x0070300600902110,
+    0x0040808800B62048,
+    0x0000810400C44420,
+    0x00080400440C0441,
+    0x8340080020840411,
+    0x0000000104208200,
+    0x0000800810D00080,
+    0x0400530411080200,
+    0x4040702400932244,
+];
+const ROOK_MAGICS: [u64; 64] = [
+    0x1080004008801020,
+    0x0840092002C03000,
+    0x1900200010400900,
+    0x0880100008000480,
+    0x4200100420080200,
+    0x8100020100080400,
+    0x0200040110886200,
+    0x0200008040220411,
+    0x0404800084400220,
+    0x0000401000402000,
+    0x0086001081220440,
+    0x0408800800100280,
+    0x000A001201040820,
+    0x8848800200840080,
+    0x4001000100040200,
+    0x0442000102105084,
+    0x9080010020804100,
+    0x0040404000201009,
+    0x0000808010002009,
+    0x2200090021D00100,
+    0x0008008008040080,
+    0x0004004002010040,
+    0x0011040008015042,
+    0x00000A0001768104,
+    0x0000800080204009,
+    0x2010004140002001,
+    0x9800200280100080,
+    0x1000100080080080,
+    0x0442000A00049020,
+    0x2100040080020080,
+    0x0800120400900148,
+    0x0010040A00128541,
+    0x2800804000800030,
+    0x1010002000400041,
+    0x4000200011004100,
+    0x0610008410800800,
+    0x0400802402800800,
+    0xC100020080800400,
+    0x0002000802000401,
+    0x0182085882000401,
+    0x0220204000808000,
+    0x2860100040024022,
+    0x0001002004110040,
+    0x99101042000A0020,
+    0x0004080004008080,
+    0x0010040002008080,
+    0x2012004881020004,
+    0x8300842444820011,
+    0x0088403882010200,
+    0x0820400080210100,
+    0x0110910040A00300,
+    0x0801100280080480,
+    0x0242009008200600,
+    0x1002000489500200,
+    0x0040800200010080,
+    0x0091800041000080,
+    0x0000209300488001,
+    0x04C1002414824001,
+    0x020020000B001041,
+    0x7000100004200901,
+    0x8002002004100802,
+    0x30010002084C0007,
+    0x0888221800813004,
+    0x4000002840840112,
+];
+
+struct Magic {
+    mask: Bits,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bits) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct SliderTables {
+    bishop: Vec<Magic>,
+    rook: Vec<Magic>,
+    attacks: Vec<Bits>,
+}
+
+static TABLES: LazyLock<SliderTables> = LazyLock::new(SliderTables::new);
+
+impl SliderTables {
+    fn new() -> Self {
+        let mut attacks = Vec::new();
+        let bishop = Self::fill(&mut attacks, &BISHOP_MAGICS, &BISHOP_DIRECTIONS);
+        let rook = Self::fill(&mut attacks, &ROOK_MAGICS, &ROOK_DIRECTIONS);
+        SliderTables {
+            bishop,
+            rook,
+            attacks,
+        }
+    }
+
+    fn fill(attacks: &mut Vec<Bits>, magics: &[u64; 64], directions: &[(i8, i8)]) -> Vec<Magic> {
+        let mut entries = Vec::with_capacity(64);
+        for (index, &magic) in magics.iter().enumerate() {
+            let square = Square::from_repr(index as i8).unwrap();
+            let mask = relevant_occupancy(square, directions);
+            let bits = mask.count_ones();
+            let entry = Magic {
+                mask,
+                magic,
+                shift: 64 - bits,
+                offset: attacks.len(),
+            };
+            attacks.resize(attacks.len() + (1 << bits), 0);
+
+            // Walk every subset of the mask with the Carry-Rippler trick.
+            let mut occupied: Bits = 0;
+            loop {
+                let attack = sliding_attacks(square, occupied, directions);
+                let slot = &mut attacks[entry.index(occupied)];
+                assert!(
+                    *slot == 0 || *slot == attack,
+                    "magic number for {square} is not collision free"
+                );
+                *slot = attack;
+                occupied = occupied.wrapping_sub(mask) & mask;
+                if occupied == 0 {
+                    break;
+                }
+            }
+            entries.push(entry);
+        }
+        entries
+    }
+}
+
+pub fn bishop_attacks(square: Square, occupied: Bits) -> Bits {
+    let tables = &*TABLES;
+    tables.attacks[tables.bishop[square as usize].index(occupied)]
+}
+
+pub fn rook_attacks(square: Square, occupied: Bits) -> Bits {
+    let tables = &*TABLES;
+    tables.attacks[tables.rook[square as usize].index(occupied)]
+}
+
+/// Walks each ray from `square` until it leaves the board or hits an occupied
+/// square, which is included in the result. Slow, but obviously correct.
+pub fn sliding_attacks(square: Square, occupied: Bits, directions: &[(i8, i8)]) -> Bits {
+    let mut attacks = 0;
+    for &(file_step, rank_step) in directions {
+        let mut file = square.file() as i8 + file_step;
+        let mut rank = square.rank() as i8 + rank_step;
+        while let Some(target) = Square::from_file_rank(file as u8, rank as u8) {
+            attacks |= 1 << target as u8;
+            if occupied & 1 << target as u8 != 0 {
+                break;
+            }
+            file += file_step;
+            rank += rank_step;
+        }
+    }
+    attacks
+}
+
+/// The squares whose occupancy can change the attack set of a slider on
+/// `square`: every ray square except the last one before the edge.
+fn relevant_occupancy(square: Square, directions: &[(i8, i8)]) -> Bits {
+    let mut mask = 0;
+    for &(file_step, rank_step) in directions {
+        let mut file = square.file() as i8 + file_step;
+        let mut rank = square.rank() as i8 + rank_step;
+        while (0..8).contains(&(file + file_step)) && (0..8).contains(&(rank + rank_step)) {
+            mask |= 1 << (file * 8 + rank);
+            file += file_step;
+            rank += rank_step;
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    fn assert_matches_reference(directions: &[(i8, i8)], lookup: fn(Square, Bits) -> Bits) {
+        for square in Square::iter() {
+            let mask = relevant_occupancy(square, directions);
+            let mut occupied: Bits = 0;
+            loop {
+                // Pieces outside the relevant mask must not change the result.
+                let noise = !mask & 0x8142_2418_1824_4281;
+                assert_eq!(
+                    lookup(square, occupied | noise),
+                    sliding_attacks(square, occupied | noise, directions),
+                    "{square} with occupancy {occupied:#x}"
+                );
+                occupied = occupied.wrapping_sub(mask) & mask;
+                if occupied == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bishop_attacks_match_reference() {
+        assert_matches_reference(&BISHOP_DIRECTIONS, bishop_attacks);
+    }
+
+    #[test]
+    fn test_rook_attacks_match_reference() {
+        assert_matches_reference(&ROOK_DIRECTIONS, rook_attacks);
+    }
+
+    #[test]
+    fn test_relevant_occupancy_sizes() {
+        use Square::*;
+        assert_eq!(relevant_occupancy(A1, &ROOK_DIRECTIONS).count_ones(), 12);
+        assert_eq!(relevant_occupancy(E4, &ROOK_DIRECTIONS).count_ones(), 10);
+        assert_eq!(relevant_occupancy(A1, &BISHOP_DIRECTIONS).count_ones(), 6);
+        assert_eq!(relevant_occupancy(E4, &BISHOP_DIRECTIONS).count_ones(), 9);
+    }
+}
@@ -0,0 +1,576 @@
+use crate::board::bitboard::{
+    attacked_squares_for, en_passant_target, line_through, pawn_pushes, promotion_rank,
+    squares_between, Bitboard,
+};
+use crate::board::moves::{Move, MoveList};
+use crate::board::state::{CastlingRights, Color, PieceType, Square, State};
+
+const PROMOTIONS: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Knight,
+    PieceType::Rook,
+    PieceType::Bishop,
+];
+
+/// Restrictions on the moves a generator emits. Pseudo-legal generation uses
+/// `MoveFilter::NONE`; legal generation derives the filter from the checkers
+/// and pins of the position. Quiescence search asks for captures only.
+struct MoveFilter {
+    legal: bool,
+    // Only captures and promotions.
+    captures_only: bool,
+    // Squares a non-king move must land on: the checking piece or a square that
+    // blocks its ray when in single check, every square otherwise.
+    check_mask: Bitboard,
+    pinned: Bitboard,
+    double_check: bool,
+}
+
+impl MoveFilter {
+    const NONE: MoveFilter = MoveFilter {
+        legal: false,
+        captures_only: false,
+        check_mask: Bitboard::from_bits(!0),
+        pinned: Bitboard::EMPTY,
+        double_check: false,
+    };
+}
+
+impl State {
+    /// Every move the side to move can make without regard to its own king:
+    /// the list may contain moves that leave the king in check. Castling is the
+    /// exception, it is only generated when the king does not start on, pass
+    /// through or land on an attacked square.
+    pub fn pseudo_legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        generate_moves(self, &MoveFilter::NONE, &mut moves);
+        moves
+    }
+
+    /// Every legal move for the side to move. Moves are checked against the
+    /// checkers and pins of the position while they are generated, so no move
+    /// is ever made to test it.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        generate_moves(self, &self.legal_filter(false), &mut moves);
+        moves
+    }
+
+    /// The legal captures and promotions, the moves that change the material
+    /// balance.
+    pub fn legal_captures(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        generate_moves(self, &self.legal_filter(true), &mut moves);
+        moves
+    }
+
+    /// All pieces of either color that attack `square` when the board holds the
+    /// pieces in `occupied`.
+    pub fn attackers_to(&self, square: Square, occupied: Bitboard) -> Bitboard {
+        use PieceType::*;
+        let bishops_queens = self.pieces_of_both(Bishop) | self.pieces_of_both(Queen);
+        let rooks_queens = self.pieces_of_both(Rook) | self.pieces_of_both(Queen);
+
+        // A white pawn attacks `square` from where a black pawn on `square`
+        // would attack, and vice versa.
+        (attacked_squares_for(Pawn, Color::Black, square, occupied)
+            & self.get_bitboard_for(Pawn, Color::White))
+            | (attacked_squares_for(Pawn, Color::White, square, occupied)
+                & self.get_bitboard_for(Pawn, Color::Black))
+            | (attacked_squares_for(Knight, Color::White, square, occupied)
+                & self.pieces_of_both(Knight))
+            | (attacked_squares_for(King, Color::White, square, occupied)
+                & self.pieces_of_both(King))
+            | (attacked_squares_for(Bishop, Color::White, square, occupied) & bishops_queens)
+            | (attacked_squares_for(Rook, Color::White, square, occupied) & rooks_queens)
+    }
+
+    pub fn is_square_attacked(&self, square: Square, by: Color) -> bool {
+        !(self.attackers_to(square, self.occupied()) & self.occupied_by(by)).is_empty()
+    }
+
+    pub fn in_check(&self) -> bool {
+        !self.checkers().is_empty()
+    }
+
+    /// The enemy pieces giving check to the king of the side to move.
+    pub fn checkers(&self) -> Bitboard {
+        let color = self.side_to_move();
+        self.attackers_to(self.king_square(color), self.occupied())
+            & self.occupied_by(color.opposite())
+    }
+
+    /// The `color` pieces that are the only piece between their king and an
+    /// enemy slider aiming at it. Such a piece may only move along the line
+    /// through the king and the slider.
+    pub fn pinned(&self, color: Color) -> Bitboard {
+        use PieceType::*;
+        let king = self.king_square(color);
+        let enemy = color.opposite();
+        let queens = self.get_bitboard_for(Queen, enemy);
+        let enemies = self.occupied_by(enemy);
+
+        // Sliders that would attack the king if only enemy pieces were on the board.
+        let snipers = (attacked_squares_for(Rook, enemy, king, enemies)
+            & (self.get_bitboard_for(Rook, enemy) | queens))
+            | (attacked_squares_for(Bishop, enemy, king, enemies)
+                & (self.get_bitboard_for(Bishop, enemy) | queens));
+
+        let own = self.occupied_by(color);
+        let mut pinned = Bitboard::EMPTY;
+        for sniper in snipers.squares() {
+            let blockers = squares_between(king, sniper) & self.occupied();
+            if blockers.count() == 1 {
+                pinned |= blockers & own;
+            }
+        }
+        pinned
+    }
+
+    fn legal_filter(&self, captures_only: bool) -> MoveFilter {
+        let color = self.side_to_move();
+        let king = self.king_square(color);
+        let checkers = self.checkers();
+        let check_mask = match checkers.count() {
+            0 => Bitboard::from_bits(!0),
+            1 => {
+                let checker = checkers.squares().next().unwrap();
+                squares_between(king, checker) | checkers
+            }
+            _ => Bitboard::EMPTY,
+        };
+        MoveFilter {
+            legal: true,
+            captures_only,
+            check_mask,
+            pinned: self.pinned(color),
+            double_check: checkers.count() > 1,
+        }
+    }
+
+    fn pieces_of_both(&self, piece: PieceType) -> Bitboard {
+        self.get_bitboard_for(piece, Color::White) | self.get_bitboard_for(piece, Color::Black)
+    }
+}
+
+fn generate_moves(state: &State, filter: &MoveFilter, moves: &mut MoveList) {
+    generate_king_moves(state, filter, moves);
+    if filter.double_check {
+        return;
+    }
+    generate_pawn_moves(state, filter, moves);
+    for piece in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        generate_piece_moves(state, piece, filter, moves);
+    }
+    if !filter.captures_only {
+        generate_castling_moves(state, moves);
+    }
+}
+
+/// Where the piece on `from` may go given the check mask and its pin, if any.
+fn allowed_targets(state: &State, from: Square, filter: &MoveFilter) -> Bitboard {
+    if filter.pinned.square_occupied(from) {
+        let king = state.king_square(state.side_to_move());
+        filter.check_mask & line_through(king, from)
+    } else {
+        filter.check_mask
+    }
+}
+
+fn generate_pawn_moves(state: &State, filter: &MoveFilter, moves: &mut MoveList) {
+    let color = state.side_to_move();
+    let occupied = state.occupied();
+    let enemies = state.occupied_by(color.opposite());
+    let last_rank = promotion_rank(color);
+
+    for from in state.get_bitboard_for(PieceType::Pawn, color).squares() {
+        let allowed = allowed_targets(state, from, filter);
+        let mut pushes = pawn_pushes(color, from, occupied) & allowed;
+        if filter.captures_only {
+            pushes &= last_rank;
+        }
+        for to in pushes.squares() {
+            if last_rank.square_occupied(to) {
+                push_promotions(from, to, false, moves);
+            } else if en_passant_target(color, from, to).is_some() {
+                moves.push(Move::new(from, to, Move::DOUBLE_PUSH));
+            } else {
+                moves.push(Move::new(from, to, Move::QUIET));
+            }
+        }
+
+        let attacks = attacked_squares_for(PieceType::Pawn, color, from, occupied);
+        for to in (attacks & enemies & allowed).squares() {
+            if last_rank.square_occupied(to) {
+                push_promotions(from, to, true, moves);
+            } else {
+                moves.push(Move::new(from, to, Move::CAPTURE));
+            }
+        }
+
+        if let Some(target) = state.en_passant() {
+            let en_passant = Move::new(from, target, Move::EN_PASSANT);
+            if attacks.square_occupied(target)
+                && (!filter.legal || en_passant_is_legal(state, en_passant))
+            {
+                moves.push(en_passant);
+            }
+        }
+    }
+}
+
+/// En passant removes two pieces from the line between the king and a slider
+/// at once, and the captured pawn is not on the target square, so neither the
+/// pin nor the check mask rules apply. The resulting occupancy is checked
+/// directly instead.
+fn en_passant_is_legal(state: &State, en_passant: Move) -> bool {
+    let color = state.side_to_move();
+    let king = state.king_square(color);
+    let captured = Square::from_file_rank(en_passant.to().file(), en_passant.from().rank())
+        .expect("the captured pawn stands next to the capturing pawn");
+
+    let mut occupied = state.occupied();
+    occupied.clear_square(en_passant.from());
+    occupied.clear_square(captured);
+    occupied.set_square(en_passant.to());
+
+    let mut enemies = state.occupied_by(color.opposite());
+    enemies.clear_square(captured);
+    (state.attackers_to(king, occupied) & enemies).is_empty()
+}
+
+fn push_promotions(from: Square, to: Square, capture: bool, moves: &mut MoveList) {
+    for piece in PROMOTIONS {
+        moves.push(Move::promotion(from, to, piece, capture));
+    }
+}
+
+fn generate_piece_moves(
+    state: &State,
+    piece: PieceType,
+    filter: &MoveFilter,
+    moves: &mut MoveList,
+) {
+    let color = state.side_to_move();
+    let occupied = state.occupied();
+    let enemies = state.occupied_by(color.opposite());
+    let reachable = if filter.captures_only {
+        enemies
+    } else {
+        !state.occupied_by(color)
+    };
+
+    for from in state.get_bitboard_for(piece, color).squares() {
+        let targets = attacked_squares_for(piece, color, from, occupied)
+            & reachable
+            & allowed_targets(state, from, filter);
+        push_targets(from, targets, enemies, moves);
+    }
+}
+
+fn generate_king_moves(state: &State, filter: &MoveFilter, moves: &mut MoveList) {
+    let color = state.side_to_move();
+    let king = state.king_square(color);
+    let occupied = state.occupied();
+    let enemies = state.occupied_by(color.opposite());
+
+    let mut targets =
+        attacked_squares_for(PieceType::King, color, king, occupied) & !state.occupied_by(color);
+    if filter.captures_only {
+        targets &= enemies;
+    }
+    if filter.legal {
+        // Without the king on the board, a slider checking it also attacks the
+        // squares behind it.
+        let mut without_king = occupied;
+        without_king.clear_square(king);
+        for to in targets.squares() {
+            if !(state.attackers_to(to, without_king) & enemies).is_empty() {
+                targets.clear_square(to);
+            }
+        }
+    }
+    push_targets(king, targets, enemies, moves);
+}
+
+fn push_targets(from: Square, targets: Bitboard, enemies: Bitboard, moves: &mut MoveList) {
+    for to in (targets & enemies).squares() {
+        moves.push(Move::new(from, to, Move::CAPTURE));
+    }
+    for to in (targets & !enemies).squares() {
+        moves.push(Move::new(from, to, Move::QUIET));
+    }
+}
+
+fn generate_castling_moves(state: &State, moves: &mut MoveList) {
+    use Square::*;
+    let color = state.side_to_move();
+    let enemy = color.opposite();
+    let (king, kingside, queenside) = match color {
+        Color::White => (E1, [F1, G1], [D1, C1, B1]),
+        Color::Black => (E8, [F8, G8], [D8, C8, B8]),
+    };
+    let rights = state.castling_rights();
+    let occupied = state.occupied();
+
+    if !rights.contains(CastlingRights::kingside(color))
+        && !rights.contains(CastlingRights::queenside(color))
+        || state.is_square_attacked(king, enemy)
+    {
+        return;
+    }
+
+    if rights.contains(CastlingRights::kingside(color))
+        && kingside
+            .iter()
+            .all(|&square| !occupied.square_occupied(square))
+        && kingside
+            .iter()
+            .all(|&square| !state.is_square_attacked(square, enemy))
+    {
+        moves.push(Move::new(king, kingside[1], Move::KING_CASTLE));
+    }
+
+    // The b-file square has to be empty, but the king does not cross it.
+    if rights.contains(CastlingRights::queenside(color))
+        && queenside
+            .iter()
+            .all(|&square| !occupied.square_occupied(square))
+        && queenside[..2]
+            .iter()
+            .all(|&square| !state.is_square_attacked(square, enemy))
+    {
+        moves.push(Move::new(king, queenside[1], Move::QUEEN_CASTLE));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::state::PieceType::{Bishop, Knight, Queen, Rook};
+    use crate::board::state::Square::*;
+
+    fn moves_from_fen(fen: &str) -> MoveList {
+        State::from_fen(fen).unwrap().pseudo_legal_moves()
+    }
+
+    #[test]
+    fn test_start_position() {
+        let moves = State::new().pseudo_legal_moves();
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(Move::new(E2, E4, Move::DOUBLE_PUSH)));
+        assert!(moves.contains(Move::new(E2, E3, Move::QUIET)));
+        assert!(moves.contains(Move::new(B1, C3, Move::QUIET)));
+
+        let moves = moves_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(Move::new(D7, D5, Move::DOUBLE_PUSH)));
+        assert!(moves.contains(Move::new(G8, F6, Move::QUIET)));
+    }
+
+    #[test]
+    fn test_kiwipete() {
+        let moves =
+            moves_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(moves.len(), 48);
+        assert_eq!(moves.iter().filter(|m| m.is_capture()).count(), 8);
+        assert_eq!(moves.iter().filter(|m| m.is_castle()).count(), 2);
+    }
+
+    #[test]
+    fn test_pawn_captures_and_en_passant() {
+        let moves = moves_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert!(moves.contains(Move::new(E5, D6, Move::EN_PASSANT)));
+        assert!(moves.contains(Move::new(E5, E6, Move::QUIET)));
+
+        let moves = moves_from_fen("4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 1");
+        assert!(moves.contains(Move::new(E4, D3, Move::EN_PASSANT)));
+
+        let moves = moves_from_fen("4k3/8/8/8/8/2n1n3/3P4/4K3 w - - 0 1");
+        assert!(moves.contains(Move::new(D2, C3, Move::CAPTURE)));
+        assert!(moves.contains(Move::new(D2, E3, Move::CAPTURE)));
+        assert!(moves.contains(Move::new(D2, D4, Move::DOUBLE_PUSH)));
+    }
+
+    #[test]
+    fn test_promotions() {
+        let moves = moves_from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        for piece in [Queen, Rook, Bishop, Knight] {
+            assert!(moves.contains(Move::promotion(A7, A8, piece, false)));
+            assert!(moves.contains(Move::promotion(A7, B8, piece, true)));
+        }
+        assert_eq!(moves.iter().filter(|m| m.is_promotion()).count(), 8);
+
+        let moves = moves_from_fen("4k3/8/8/8/8/8/p7/4K3 b - - 0 1");
+        assert!(moves.contains(Move::promotion(A2, A1, Queen, false)));
+    }
+
+    #[test]
+    fn test_castling() {
+        let moves = moves_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert!(moves.contains(Move::new(E1, G1, Move::KING_CASTLE)));
+        assert!(moves.contains(Move::new(E1, C1, Move::QUEEN_CASTLE)));
+
+        let moves = moves_from_fen("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1");
+        assert!(!moves.contains(Move::new(E8, G8, Move::KING_CASTLE)));
+        assert!(moves.contains(Move::new(E8, C8, Move::QUEEN_CASTLE)));
+
+        // Blocked on b1, f1 attacked by the rook on f8.
+        let moves = moves_from_fen("5r1k/8/8/8/8/8/8/RN2K2R w KQ - 0 1");
+        assert!(!moves.iter().any(|m| m.is_castle()));
+
+        // The rook on b8 attacks b1, which the king does not cross.
+        let moves = moves_from_fen("1r5k/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        assert!(moves.contains(Move::new(E1, C1, Move::QUEEN_CASTLE)));
+
+        // No castling out of check.
+        let moves = moves_from_fen("4r2k/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(!moves.iter().any(|m| m.is_castle()));
+    }
+
+    #[test]
+    fn test_own_and_enemy_occupancy() {
+        let moves = moves_from_fen("4k3/8/8/8/8/8/p7/RB2K3 w - - 0 1");
+        assert!(moves.contains(Move::new(A1, A2, Move::CAPTURE)));
+        assert!(moves.contains(Move::new(B1, A2, Move::CAPTURE)));
+        assert!(moves.contains(Move::new(B1, C2, Move::QUIET)));
+        assert!(!moves.iter().any(|m| m.from() == A1 && m.to() == A3));
+        assert!(!moves.iter().any(|m| m.from() == A1 && m.to() == B1));
+    }
+
+    fn legal_from_fen(fen: &str) -> MoveList {
+        State::from_fen(fen).unwrap().legal_moves()
+    }
+
+    #[test]
+    fn test_legal_move_counts() {
+        let positions = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                20,
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                48,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 14),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                6,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                44,
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                46,
+            ),
+        ];
+        for (fen, count) in positions {
+            assert_eq!(legal_from_fen(fen).len(), count, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_checkers_and_pins() {
+        let state = State::from_fen("4k3/8/8/1b6/8/3N4/4R3/r3K3 w - - 0 1").unwrap();
+        assert_eq!(state.checkers(), Bitboard::from_squares(&[A1]));
+        assert!(state.in_check());
+        assert_eq!(state.pinned(Color::White), Bitboard::EMPTY);
+
+        let state = State::from_fen("k3r3/8/8/b7/8/2N5/4R3/4K3 w - - 0 1").unwrap();
+        assert_eq!(state.checkers(), Bitboard::EMPTY);
+        assert_eq!(
+            state.pinned(Color::White),
+            Bitboard::from_squares(&[C3, E2])
+        );
+    }
+
+    #[test]
+    fn test_pinned_pieces_move_along_the_pin() {
+        let moves = legal_from_fen("k3r3/8/8/b7/8/2N5/4R3/4K3 w - - 0 1");
+        assert!(!moves.iter().any(|m| m.from() == C3));
+        assert!(moves.contains(Move::new(E2, E8, Move::CAPTURE)));
+        assert!(moves.contains(Move::new(E2, E5, Move::QUIET)));
+        assert!(!moves.contains(Move::new(E2, D2, Move::QUIET)));
+    }
+
+    #[test]
+    fn test_check_evasions() {
+        // Block the rank or step off it; the rook cannot reach the first rank.
+        let moves = legal_from_fen("4k3/8/8/8/8/3N4/4R3/r3K3 w - - 0 1");
+        let mut text: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+        text.sort();
+        assert_eq!(text, ["d3c1", "e1d2", "e1f2"]);
+    }
+
+    #[test]
+    fn test_double_check_allows_only_king_moves() {
+        let moves = legal_from_fen("4k3/8/8/8/8/5n2/8/r3K2R w K - 0 1");
+        assert!(moves.iter().all(|m| m.from() == E1));
+        assert!(!moves.iter().any(|m| m.is_castle()));
+        assert_eq!(moves.len(), 2);
+    }
+
+    #[test]
+    fn test_king_cannot_retreat_along_checking_ray() {
+        let moves = legal_from_fen("8/8/8/8/8/8/4K2r/k7 w - - 0 1");
+        assert!(!moves.contains(Move::new(E2, D2, Move::QUIET)));
+        assert!(moves.contains(Move::new(E2, D3, Move::QUIET)));
+    }
+
+    #[test]
+    fn test_en_passant_discovered_check() {
+        // Both pawns leave the fifth rank, exposing the king to the rook.
+        let moves = legal_from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1");
+        assert!(!moves.iter().any(|m| m.is_en_passant()));
+
+        // Capturing the checking pawn en passant resolves the check.
+        let moves = legal_from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        assert!(moves.contains(Move::new(E4, D3, Move::EN_PASSANT)));
+    }
+
+    #[test]
+    fn test_attackers_to() {
+        let state = State::from_fen("4k3/8/8/3p4/2N1R3/3K4/8/8 w - - 0 1").unwrap();
+        assert_eq!(
+            state.attackers_to(E3, state.occupied()),
+            Bitboard::from_squares(&[C4, D3, E4])
+        );
+        assert_eq!(
+            state.attackers_to(C4, state.occupied()),
+            Bitboard::from_squares(&[D3, D5, E4])
+        );
+        assert!(state.is_square_attacked(E5, Color::White));
+        assert!(state.is_square_attacked(E4, Color::Black));
+        assert!(!state.in_check());
+    }
+
+    #[test]
+    fn test_legal_captures() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "4k3/8/8/8/8/8/4r3/R3K3 w Q - 0 1",
+        ] {
+            let state = State::from_fen(fen).unwrap();
+            let captures = state.legal_captures();
+            let expected: Vec<Move> = state
+                .legal_moves()
+                .iter()
+                .copied()
+                .filter(|m| m.is_capture() || m.is_promotion())
+                .collect();
+            assert_eq!(captures.len(), expected.len(), "{fen}");
+            assert!(expected.iter().all(|&m| captures.contains(m)), "{fen}");
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::board::bitboard::{en_passant_target, promotion_rank};
+use crate::board::state::{PieceType, Square, State};
+use std::error::Error;
+use std::fmt;
+
+/// A move packed into 16 bits: the from-square in bits 0-5, the to-square in
+/// bits 6-11 and four flag bits on top. The flags follow the usual layout where
+/// bit 14 marks captures and bit 15 promotions, with bits 12 and 13 selecting
+/// the promotion piece or the kind of special move.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Move {
+    data: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveParseError {
+    InvalidNotation(String),
+    EmptySquare(Square),
+    InvalidPromotion(String),
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MoveParseError::*;
+        match self {
+            InvalidNotation(text) => write!(f, "'{text}' is not a move in UCI notation"),
+            EmptySquare(square) => write!(f, "no piece of the side to move on {square}"),
+            InvalidPromotion(text) => write!(f, "invalid promotion in '{text}'"),
+        }
+    }
+}
+
+impl Error for MoveParseError {}
+
+const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+impl Move {
+    pub const NULL: Move = Move { data: 0 };
+
+    pub const QUIET: u16 = 0b0000;
+    pub const DOUBLE_PUSH: u16 = 0b0001;
+    pub const KING_CASTLE: u16 = 0b0010;
+    pub const QUEEN_CASTLE: u16 = 0b0011;
+    pub const CAPTURE: u16 = 0b0100;
+    pub const EN_PASSANT: u16 = 0b0101;
+    pub const PROMOTION: u16 = 0b1000;
+
+    pub fn new(from: Square, to: Square, flags: u16) -> Move {
+        debug_assert!(flags < 16);
+        Move {
+            data: from as u16 | (to as u16) << 6 | flags << 12,
+        }
+    }
+
+    /// A promotion to `piece`, which must be a knight, bishop, rook or queen.
+    pub fn promotion(from: Square, to: Square, piece: PieceType, capture: bool) -> Move {
+        let index = PROMOTION_PIECES
+            .iter()
+            .position(|&promotion| promotion == piece)
+            .expect("pawns promote to a knight, bishop, rook or queen") as u16;
+        let capture = if capture { Self::CAPTURE } else { 0 };
+        Move::new(from, to, Self::PROMOTION | capture | index)
+    }
+
+    /// The move with the given 16-bit encoding, as returned by `to_bits`.
+    pub const fn from_bits(data: u16) -> Move {
+        Move { data }
+    }
+
+    pub fn to_bits(self) -> u16 {
+        self.data
+    }
+
+    pub fn from(self) -> Square {
+        Square::from_repr((self.data & 0x3F) as i8).unwrap()
+    }
+
+    pub fn to(self) -> Square {
+        Square::from_repr((self.data >> 6 & 0x3F) as i8).unwrap()
+    }
+
+    pub fn flags(self) -> u16 {
+        self.data >> 12
+    }
+
+    pub fn is_null(self) -> bool {
+        self == Self::NULL
+    }
+
+    pub fn is_capture(self) -> bool {
+        self.flags() & Self::CAPTURE != 0
+    }
+
+    pub fn is_double_push(self) -> bool {
+        self.flags() == Self::DOUBLE_PUSH
+    }
+
+    pub fn is_en_passant(self) -> bool {
+        self.flags() == Self::EN_PASSANT
+    }
+
+    pub fn is_castle(self) -> bool {
+        self.flags() == Self::KING_CASTLE || self.flags() == Self::QUEEN_CASTLE
+    }
+
+    pub fn is_promotion(self) -> bool {
+        self.flags() & Self::PROMOTION != 0
+    }
+
+    pub fn promotion_piece(self) -> Option<PieceType> {
+        self.is_promotion()
+            .then(|| PROMOTION_PIECES[(self.flags() & 0b11) as usize])
+    }
+
+    /// Parses a move in UCI long algebraic notation (`e2e4`, `e7e8q`, `0000` for
+    /// the null move). The notation carries no flags, so they are derived from
+    /// the pieces in `state`; whether the move is legal is not checked.
+    pub fn from_uci(text: &str, state: &State) -> Result<Move, MoveParseError> {
+        if text == "0000" {
+            return Ok(Move::NULL);
+        }
+        let invalid = || MoveParseError::InvalidNotation(text.to_string());
+        let square = |notation: &[u8]| match notation {
+            &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Square::from_file_rank(file - b'a', rank - b'1')
+            }
+            _ => None,
+        };
+
+        let bytes = text.as_bytes();
+        if bytes.len() != 4 && bytes.len() != 5 {
+            return Err(invalid());
+        }
+        let from = square(&bytes[0..2]).ok_or_else(invalid)?;
+        let to = square(&bytes[2..4]).ok_or_else(invalid)?;
+
+        let color = state.side_to_move();
+        let piece = match state.piece_at(from) {
+            Some((piece, owner)) if owner == color => piece,
+            _ => return Err(MoveParseError::EmptySquare(from)),
+        };
+        let capture = state
+            .piece_at(to)
+            .is_some_and(|(_, owner)| owner == color.opposite());
+
+        let promotes = piece == PieceType::Pawn && promotion_rank(color).square_occupied(to);
+        if let Some(&c) = bytes.get(4) {
+            let promotion = match c {
+                b'n' => PieceType::Knight,
+                b'b' => PieceType::Bishop,
+                b'r' => PieceType::Rook,
+                b'q' => PieceType::Queen,
+                _ => return Err(MoveParseError::InvalidPromotion(text.to_string())),
+            };
+            if !promotes {
+                return Err(MoveParseError::InvalidPromotion(text.to_string()));
+            }
+            return Ok(Move::promotion(from, to, promotion, capture));
+        }
+        if promotes {
+            return Err(MoveParseError::InvalidPromotion(text.to_string()));
+        }
+
+        let flags = match piece {
+            PieceType::King if from.file() == 4 && to.file() == 6 && from.rank() == to.rank() => {
+                Self::KING_CASTLE
+            }
+            PieceType::King if from.file() == 4 && to.file() == 2 && from.rank() == to.rank() => {
+                Self::QUEEN_CASTLE
+            }
+            PieceType::Pawn if en_passant_target(color, from, to).is_some() => Self::DOUBLE_PUSH,
+            PieceType::Pawn if state.en_passant() == Some(to) && from.file() != to.file() => {
+                Self::EN_PASSANT
+            }
+            _ if capture => Self::CAPTURE,
+            _ => Self::QUIET,
+        };
+        Ok(Move::new(from, to, flags))
+    }
+}
+
+pub const MAX_MOVES: usize = 256;
+
+/// A fixed-capacity list of moves that lives on the stack. No legal chess
+/// position has more than 218 moves, so `MAX_MOVES` is never exceeded.
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [Move::NULL; MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, chess_move: Move) {
+        self.moves[self.len] = chess_move;
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, chess_move: Move) -> bool {
+        self.as_slice().contains(&chess_move)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Move> {
+        self.as_slice().iter()
+    }
+
+    pub fn as_slice(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_null() {
+            return write!(f, "0000");
+        }
+        write!(f, "{}{}", self.from(), self.to())?;
+        match self.promotion_piece() {
+            Some(PieceType::Knight) => write!(f, "n"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Queen) => write!(f, "q"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::state::PieceType::{Knight, Queen, Rook};
+    use crate::board::state::Square::*;
+
+    #[test]
+    fn test_move_fits_in_16_bits() {
+        assert_eq!(std::mem::size_of::<Move>(), 2);
+    }
+
+    #[test]
+    fn test_move_encoding() {
+        let quiet = Move::new(G1, F3, Move::QUIET);
+        assert_eq!(quiet.from(), G1);
+        assert_eq!(quiet.to(), F3);
+        assert!(!quiet.is_capture() && !quiet.is_promotion() && !quiet.is_castle());
+
+        let capture = Move::new(H8, A1, Move::CAPTURE);
+        assert_eq!((capture.from(), capture.to()), (H8, A1));
+        assert!(capture.is_capture());
+        assert_eq!(capture.promotion_piece(), None);
+
+        assert!(Move::new(E2, E4, Move::DOUBLE_PUSH).is_double_push());
+        assert!(Move::new(E1, G1, Move::KING_CASTLE).is_castle());
+        assert!(Move::new(E8, C8, Move::QUEEN_CASTLE).is_castle());
+
+        let en_passant = Move::new(E5, D6, Move::EN_PASSANT);
+        assert!(en_passant.is_en_passant() && en_passant.is_capture());
+
+        let promotion = Move::promotion(B7, A8, Rook, true);
+        assert_eq!((promotion.from(), promotion.to()), (B7, A8));
+        assert!(promotion.is_promotion() && promotion.is_capture());
+        assert_eq!(promotion.promotion_piece(), Some(Rook));
+        assert!(!promotion.is_en_passant() && !promotion.is_castle());
+
+        let promotion = Move::promotion(B2, B1, Knight, false);
+        assert!(promotion.is_promotion() && !promotion.is_capture());
+        assert_eq!(promotion.promotion_piece(), Some(Knight));
+    }
+
+    #[test]
+    fn test_move_list() {
+        let mut list = MoveList::new();
+        assert!(list.is_empty());
+        list.push(Move::new(E2, E4, Move::DOUBLE_PUSH));
+        list.push(Move::new(G1, F3, Move::QUIET));
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(Move::new(G1, F3, Move::QUIET)));
+        assert!(!list.contains(Move::new(G1, H3, Move::QUIET)));
+        let text: Vec<String> = list.iter().map(|m| m.to_string()).collect();
+        assert_eq!(text, ["e2e4", "g1f3"]);
+    }
+
+    #[test]
+    fn test_move_display() {
+        assert_eq!(Move::new(E2, E4, Move::DOUBLE_PUSH).to_string(), "e2e4");
+        assert_eq!(Move::new(E1, G1, Move::KING_CASTLE).to_string(), "e1g1");
+        assert_eq!(Move::promotion(E7, E8, Queen, false).to_string(), "e7e8q");
+        assert_eq!(Move::promotion(B2, A1, Knight, true).to_string(), "b2a1n");
+        assert_eq!(Move::NULL.to_string(), "0000");
+    }
+
+    #[test]
+    fn test_move_from_uci() {
+        let state = State::new();
+        let parse = |text| Move::from_uci(text, &state);
+        assert_eq!(parse("e2e4"), Ok(Move::new(E2, E4, Move::DOUBLE_PUSH)));
+        assert_eq!(parse("e2e3"), Ok(Move::new(E2, E3, Move::QUIET)));
+        assert_eq!(parse("g1f3"), Ok(Move::new(G1, F3, Move::QUIET)));
+        assert_eq!(parse("0000"), Ok(Move::NULL));
+
+        let state = State::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let parse = |text| Move::from_uci(text, &state);
+        assert_eq!(parse("e1g1"), Ok(Move::new(E1, G1, Move::KING_CASTLE)));
+        assert_eq!(parse("e1c1"), Ok(Move::new(E1, C1, Move::QUEEN_CASTLE)));
+        assert_eq!(parse("e5d6"), Ok(Move::new(E5, D6, Move::EN_PASSANT)));
+        assert_eq!(parse("a1a8"), Ok(Move::new(A1, A8, Move::CAPTURE)));
+        assert_eq!(parse("b7b8q"), Ok(Move::promotion(B7, B8, Queen, false)));
+        assert_eq!(parse("b7a8n"), Ok(Move::promotion(B7, A8, Knight, true)));
+        for text in ["e5d6", "b7b8q", "b7a8n", "e1g1"] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_move_from_uci_errors() {
+        use MoveParseError::*;
+        let state = State::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let parse = |text| Move::from_uci(text, &state);
+        assert_eq!(parse("e2"), Err(InvalidNotation("e2".into())));
+        assert_eq!(parse("e2e9"), Err(InvalidNotation("e2e9".into())));
+        assert_eq!(parse("i1e2"), Err(InvalidNotation("i1e2".into())));
+        assert_eq!(parse("e2e4"), Err(EmptySquare(E2)));
+        assert_eq!(parse("e8e7"), Err(EmptySquare(E8)));
+        assert_eq!(parse("b7b8"), Err(InvalidPromotion("b7b8".into())));
+        assert_eq!(parse("b7b8k"), Err(InvalidPromotion("b7b8k".into())));
+        assert_eq!(parse("e1e2q"), Err(InvalidPromotion("e1e2q".into())));
+    }
+}
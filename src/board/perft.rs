@@ -0,0 +1,164 @@
+use crate::board::moves::Move;
+use crate::board::state::State;
+use std::io::{self, Write};
+
+/// Counts the leaf nodes of the legal move tree `depth` plies deep.
+pub fn perft(state: &mut State, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = state.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for &chess_move in &moves {
+        state.make_move(chess_move);
+        nodes += perft(state, depth - 1);
+        state.unmake_move();
+    }
+    nodes
+}
+
+/// Runs `perft` below every root move and reports each count, so a mismatch
+/// with a reference engine can be narrowed down to a single move.
+pub fn divide(state: &mut State, depth: u32) -> Vec<(Move, u64)> {
+    let mut counts = Vec::new();
+    if depth == 0 {
+        return counts;
+    }
+    for &chess_move in &state.legal_moves() {
+        state.make_move(chess_move);
+        counts.push((chess_move, perft(state, depth - 1)));
+        state.unmake_move();
+    }
+    counts
+}
+
+/// Prints the `divide` counts one root move per line, followed by the total.
+pub fn print_divide(state: &mut State, depth: u32, out: &mut impl Write) -> io::Result<u64> {
+    let counts = divide(state, depth);
+    for (chess_move, nodes) in &counts {
+        writeln!(out, "{chess_move}: {nodes}")?;
+    }
+    let total = counts.iter().map(|(_, nodes)| nodes).sum();
+    writeln!(out)?;
+    writeln!(out, "Nodes searched: {total}")?;
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::state::START_FEN;
+
+    // Reference counts from the Chess Programming Wiki perft results page.
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_4_MIRRORED: &str =
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let mut state = State::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            let depth = depth as u32 + 1;
+            assert_eq!(perft(&mut state, depth), nodes, "{fen} at depth {depth}");
+        }
+        assert_eq!(state, State::from_fen(fen).unwrap());
+    }
+
+    #[test]
+    fn test_perft_start_position() {
+        assert_perft(START_FEN, &[20, 400, 8_902, 197_281]);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        assert_perft(KIWIPETE, &[48, 2_039, 97_862]);
+    }
+
+    #[test]
+    fn test_perft_position_3() {
+        assert_perft(POSITION_3, &[14, 191, 2_812, 43_238, 674_624]);
+    }
+
+    #[test]
+    fn test_perft_position_4() {
+        assert_perft(POSITION_4, &[6, 264, 9_467, 422_333]);
+        assert_perft(POSITION_4_MIRRORED, &[6, 264, 9_467, 422_333]);
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        assert_perft(POSITION_5, &[44, 1_486, 62_379]);
+    }
+
+    #[test]
+    fn test_perft_position_6() {
+        assert_perft(POSITION_6, &[46, 2_079, 89_890]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_start_position_deep() {
+        assert_perft(
+            START_FEN,
+            &[20, 400, 8_902, 197_281, 4_865_609, 119_060_324],
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_kiwipete_deep() {
+        assert_perft(KIWIPETE, &[48, 2_039, 97_862, 4_085_603, 193_690_690]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_position_3_deep() {
+        assert_perft(POSITION_3, &[14, 191, 2_812, 43_238, 674_624, 11_030_083]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_position_4_deep() {
+        assert_perft(POSITION_4, &[6, 264, 9_467, 422_333, 15_833_292]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_position_5_deep() {
+        assert_perft(POSITION_5, &[44, 1_486, 62_379, 2_103_487, 89_941_194]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_position_6_deep() {
+        assert_perft(POSITION_6, &[46, 2_079, 89_890, 3_894_594, 164_075_551]);
+    }
+
+    #[test]
+    fn test_divide() {
+        let mut state = State::from_fen(KIWIPETE).unwrap();
+        let counts = divide(&mut state, 2);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2_039);
+        let castle = counts
+            .iter()
+            .find(|(m, _)| m.to_string() == "e1g1")
+            .unwrap();
+        assert_eq!(castle.1, 43);
+
+        let mut out = Vec::new();
+        let total = print_divide(&mut State::new(), 1, &mut out).unwrap();
+        assert_eq!(total, 20);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("e2e4: 1\n"));
+        assert!(out.ends_with("\nNodes searched: 20\n"));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::board::bitboard::Bitboard;
+use crate::board::moves::Move;
+use crate::board::state::{Color, PieceType, Square, State};
+use strum::IntoEnumIterator;
+
+/// Piece values for exchange evaluation, indexed by `PieceType`. The king is
+/// worth more than everything else together, so capturing it ends any
+/// exchange.
+pub const SEE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 20_000];
+
+// No exchange on one square has more captures than there are pieces.
+const MAX_EXCHANGE: usize = 32;
+
+impl State {
+    /// The material the side to move wins, or loses when negative, by playing
+    /// `chess_move` and then exchanging on its target square for as long as it
+    /// pays off for either side. Each side recaptures with its least valuable
+    /// piece, and sliders lined up behind a capturing piece join in once it
+    /// has left. Pins are not taken into account.
+    pub fn see(&self, chess_move: Move) -> i32 {
+        let to = chess_move.to();
+        let from = chess_move.from();
+        let (mut attacker, color) = self
+            .piece_at(from)
+            .expect("a move starts on a square holding a piece of the side to move");
+
+        let mut occupied = self.occupied();
+        let mut gain = [0; MAX_EXCHANGE];
+        gain[0] = if chess_move.is_en_passant() {
+            let captured = Square::from_file_rank(to.file(), from.rank()).unwrap();
+            occupied.clear_square(captured);
+            SEE_VALUES[PieceType::Pawn as usize]
+        } else {
+            self.piece_at(to)
+                .map_or(0, |(piece, _)| SEE_VALUES[piece as usize])
+        };
+        if let Some(promotion) = chess_move.promotion_piece() {
+            gain[0] += SEE_VALUES[promotion as usize] - SEE_VALUES[PieceType::Pawn as usize];
+            attacker = promotion;
+        }
+        occupied.clear_square(from);
+
+        // gain[n] is what the side making the nth capture has won if the
+        // exchange stops right after it.
+        let mut depth = 0;
+        let mut side = color.opposite();
+        loop {
+            let attackers = self.attackers_to(to, occupied) & occupied;
+            let Some((piece, square)) = least_valuable(self, attackers & self.occupied_by(side))
+            else {
+                break;
+            };
+            // The king may only capture when nothing recaptures.
+            let mut after = occupied;
+            after.clear_square(square);
+            if piece == PieceType::King
+                && !(self.attackers_to(to, after) & after & self.occupied_by(side.opposite()))
+                    .is_empty()
+            {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = SEE_VALUES[attacker as usize] - gain[depth - 1];
+            occupied = after;
+            attacker = piece;
+            side = side.opposite();
+            if depth == MAX_EXCHANGE - 1 {
+                break;
+            }
+        }
+
+        // Either side may decline to continue the exchange when that is better.
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    /// Whether `chess_move` wins at least `threshold` in the exchange that
+    /// follows it, as computed by [`State::see`].
+    pub fn see_ge(&self, chess_move: Move, threshold: i32) -> bool {
+        self.see(chess_move) >= threshold
+    }
+}
+
+fn least_valuable(state: &State, attackers: Bitboard) -> Option<(PieceType, Square)> {
+    PieceType::iter().find_map(|piece| {
+        let pieces = attackers
+            & (state.get_bitboard_for(piece, Color::White)
+                | state.get_bitboard_for(piece, Color::Black));
+        pieces.squares().next().map(|square| (piece, square))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see(fen: &str, text: &str) -> i32 {
+        let state = State::from_fen(fen).unwrap();
+        state.see(Move::from_uci(text, &state).unwrap())
+    }
+
+    #[test]
+    fn test_undefended_capture() {
+        assert_eq!(
+            see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+            100
+        );
+    }
+
+    #[test]
+    fn test_defended_pieces() {
+        // Nxe5 dxe5 trades the knight for a pawn.
+        assert_eq!(
+            see("4k3/8/3p4/4p3/8/5N2/8/4K3 w - - 0 1", "f3e5"),
+            100 - 320
+        );
+        // exd5 cxd5 wins the knight for a pawn.
+        assert_eq!(
+            see("4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"),
+            320 - 100
+        );
+    }
+
+    #[test]
+    fn test_x_rays() {
+        // The doubled rooks win the pawn: Rxe5 Rxe5 Rxe5.
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4R1K1 w - - 0 1", "e2e5"), 100);
+        // With one rook less the capture loses the rook.
+        assert_eq!(
+            see("4r1k1/8/8/4p3/8/8/4R3/6K1 w - - 0 1", "e2e5"),
+            100 - 500
+        );
+        // Bxe5 fxe5 Qxe5: the queen behind the bishop takes back.
+        assert_eq!(
+            see("6k1/8/5p2/4p3/8/8/1B6/Q5K1 w - - 0 1", "b2e5"),
+            100 - 330 + 100
+        );
+    }
+
+    #[test]
+    fn test_king_recaptures_only_when_safe() {
+        assert_eq!(see("8/8/8/4pk2/8/8/4R3/4K3 w - - 0 1", "e2e5"), 100 - 500);
+        assert_eq!(see("8/8/8/4pk2/8/8/4R3/4RK2 w - - 0 1", "e2e5"), 100);
+    }
+
+    #[test]
+    fn test_quiet_and_special_moves() {
+        // A knight moving next to a pawn is lost, elsewhere nothing happens.
+        assert_eq!(see("4k3/8/3p4/8/8/5N2/8/4K3 w - - 0 1", "f3e5"), -320);
+        assert_eq!(see("4k3/8/3p4/8/8/5N2/8/4K3 w - - 0 1", "f3g5"), 0);
+        // En passant wins a pawn, promotions win the piece promoted to.
+        assert_eq!(see("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1", "d5e6"), 100);
+        assert_eq!(see("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+        assert_eq!(see("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), 800 - 900);
+    }
+
+    #[test]
+    fn test_see_ge() {
+        let state = State::from_fen("4k3/8/3p4/8/8/5N2/8/4K3 w - - 0 1").unwrap();
+        let knight_move = Move::from_uci("f3e5", &state).unwrap();
+        assert!(state.see_ge(knight_move, -320));
+        assert!(!state.see_ge(knight_move, 0));
+    }
+}
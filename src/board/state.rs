@@ -3,10 +3,22 @@ use crate::board::bitboard::{
     Q_B_INIT, Q_W_INIT, R_B_INIT, R_W_INIT,
 };
 
-use strum::EnumCount;
-use strum_macros::{EnumCount, EnumIter};
+#[cfg(feature = "nnue")]
+use crate::eval::nnue::{self, Accumulator, Network};
+use crate::eval::terms::EvalTerms;
+use std::fmt;
+#[cfg(feature = "nnue")]
+use std::sync::Arc;
+use strum::{EnumCount, IntoEnumIterator};
+use strum_macros::{EnumCount, EnumIter, FromRepr};
 
-#[derive(EnumIter, Clone, Copy)]
+mod fen;
+mod make_move;
+mod zobrist;
+
+pub use fen::{FenError, START_FEN};
+
+#[derive(EnumIter, FromRepr, Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(i8)]
 pub enum Square {
     A1,
@@ -75,7 +87,35 @@ pub enum Square {
     H8,
 }
 
-#[derive(EnumIter, EnumCount, Clone, Copy, Debug)]
+impl Square {
+    pub fn from_file_rank(file: u8, rank: u8) -> Option<Self> {
+        if file > 7 || rank > 7 {
+            return None;
+        }
+        Self::from_repr((file * 8 + rank) as i8)
+    }
+
+    pub fn file(self) -> u8 {
+        self as u8 / 8
+    }
+
+    pub fn rank(self) -> u8 {
+        self as u8 % 8
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            (b'a' + self.file()) as char,
+            (b'1' + self.rank()) as char
+        )
+    }
+}
+
+#[derive(EnumIter, EnumCount, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PieceType {
     Pawn,
     Knight,
@@ -85,18 +125,120 @@ pub enum PieceType {
     King,
 }
 
-#[derive(EnumIter, EnumCount, Clone, Copy, Debug)]
+#[derive(EnumIter, EnumCount, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Color {
     White,
     Black,
 }
+
+impl Color {
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct CastlingRights {
+    bits: u8,
+}
+
+impl CastlingRights {
+    pub const NONE: CastlingRights = CastlingRights { bits: 0 };
+    pub const WHITE_KINGSIDE: CastlingRights = CastlingRights { bits: 1 };
+    pub const WHITE_QUEENSIDE: CastlingRights = CastlingRights { bits: 2 };
+    pub const BLACK_KINGSIDE: CastlingRights = CastlingRights { bits: 4 };
+    pub const BLACK_QUEENSIDE: CastlingRights = CastlingRights { bits: 8 };
+    pub const WHITE: CastlingRights = CastlingRights { bits: 3 };
+    pub const BLACK: CastlingRights = CastlingRights { bits: 12 };
+    pub const ALL: CastlingRights = CastlingRights { bits: 15 };
+
+    pub fn contains(self, rights: CastlingRights) -> bool {
+        self.bits & rights.bits == rights.bits
+    }
+
+    pub fn kingside(color: Color) -> CastlingRights {
+        match color {
+            Color::White => Self::WHITE_KINGSIDE,
+            Color::Black => Self::BLACK_KINGSIDE,
+        }
+    }
+
+    pub fn queenside(color: Color) -> CastlingRights {
+        match color {
+            Color::White => Self::WHITE_QUEENSIDE,
+            Color::Black => Self::BLACK_QUEENSIDE,
+        }
+    }
+
+    pub fn insert(&mut self, rights: CastlingRights) {
+        self.bits |= rights.bits;
+    }
+
+    pub fn remove(&mut self, rights: CastlingRights) {
+        self.bits &= !rights.bits;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+}
+
+/// A structural inconsistency in a `State`, as reported by [`State::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    OverlappingPieces(Square),
+    KingCount(Color, u32),
+    PawnOnBackRank(Square),
+    CastlingRightsWithoutPieces(CastlingRights),
+    InvalidEnPassant(Square),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use StateError::*;
+        match self {
+            OverlappingPieces(square) => write!(f, "more than one piece on {square}"),
+            KingCount(color, count) => write!(f, "{color:?} has {count} kings instead of 1"),
+            PawnOnBackRank(square) => write!(f, "pawn on back rank square {square}"),
+            CastlingRightsWithoutPieces(rights) => write!(
+                f,
+                "castling rights {rights:?} without king and rook on their initial squares"
+            ),
+            InvalidEnPassant(square) => write!(f, "{square} cannot be an en passant square"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct State {
     bitboards: [Bitboard; PieceType::COUNT * Color::COUNT],
+    side_to_move: Color,
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u16,
+    fullmove_number: u16,
+    history: Vec<make_move::Undo>,
+    hash: u64,
+    pawn_hash: u64,
+    eval_terms: EvalTerms,
+    #[cfg(feature = "nnue")]
+    accumulator: Option<Accumulator>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
     pub fn new() -> Self {
-        State {
+        let mut state = State {
             bitboards: [
                 Bitboard::from_squares(&P_W_INIT),
                 Bitboard::from_squares(&N_W_INIT),
@@ -111,6 +253,40 @@ impl State {
                 Bitboard::from_squares(&Q_B_INIT),
                 Bitboard::from_squares(&K_B_INIT),
             ],
+            side_to_move: Color::White,
+            castling_rights: CastlingRights::ALL,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: Vec::new(),
+            hash: 0,
+            pawn_hash: 0,
+            eval_terms: EvalTerms::default(),
+            #[cfg(feature = "nnue")]
+            accumulator: None,
+        };
+        state.hash = state.compute_hash();
+        state.pawn_hash = state.compute_pawn_hash();
+        state.eval_terms = EvalTerms::compute(&state);
+        #[cfg(feature = "nnue")]
+        state.set_network(nnue::network());
+        state
+    }
+
+    fn empty() -> Self {
+        State {
+            bitboards: [Bitboard::from_bits(0); PieceType::COUNT * Color::COUNT],
+            side_to_move: Color::White,
+            castling_rights: CastlingRights::NONE,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: Vec::new(),
+            hash: 0,
+            pawn_hash: 0,
+            eval_terms: EvalTerms::default(),
+            #[cfg(feature = "nnue")]
+            accumulator: None,
         }
     }
 
@@ -118,6 +294,169 @@ impl State {
         self.bitboards[self.get_index_for(piece, color)]
     }
 
+    pub fn occupied_by(&self, color: Color) -> Bitboard {
+        PieceType::iter().fold(Bitboard::EMPTY, |occupied, piece| {
+            occupied | self.get_bitboard_for(piece, color)
+        })
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.occupied_by(Color::White) | self.occupied_by(Color::Black)
+    }
+
+    pub fn king_square(&self, color: Color) -> Square {
+        self.get_bitboard_for(PieceType::King, color)
+            .squares()
+            .next()
+            .expect("every position has a king of each color")
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<(PieceType, Color)> {
+        PieceType::iter()
+            .flat_map(|piece| Color::iter().map(move |color| (piece, color)))
+            .find(|&(piece, color)| self.get_bitboard_for(piece, color).square_occupied(square))
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling_rights
+    }
+
+    pub fn en_passant(&self) -> Option<Square> {
+        self.en_passant
+    }
+
+    pub fn halfmove_clock(&self) -> u16 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u16 {
+        self.fullmove_number
+    }
+
+    /// The running sums of the piece evaluation terms.
+    pub fn eval_terms(&self) -> EvalTerms {
+        self.eval_terms
+    }
+
+    /// Evaluates with `network` from now on, or with the hand-crafted
+    /// evaluation when it is `None`.
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.accumulator = network.map(|network| Accumulator::new(network, self));
+    }
+
+    /// The network accumulators, when a network evaluates the position.
+    #[cfg(feature = "nnue")]
+    pub fn accumulator(&self) -> Option<&Accumulator> {
+        self.accumulator.as_ref()
+    }
+
+    /// Checks the invariants every reachable position satisfies: pieces do not
+    /// share squares, each side has exactly one king, no pawn stands on the first
+    /// or eighth rank, castling rights are backed by an unmoved king and rook, and
+    /// the en passant square lies directly behind a pawn that just made a double
+    /// push.
+    pub fn validate(&self) -> Result<(), StateError> {
+        use Square::*;
+
+        let mut occupied: u64 = 0;
+        for bitboard in &self.bitboards {
+            let overlap = occupied & bitboard.get_bits();
+            if overlap != 0 {
+                let square = Square::from_repr(overlap.trailing_zeros() as i8).unwrap();
+                return Err(StateError::OverlappingPieces(square));
+            }
+            occupied |= bitboard.get_bits();
+        }
+
+        for color in Color::iter() {
+            let count = self
+                .get_bitboard_for(PieceType::King, color)
+                .get_bits()
+                .count_ones();
+            if count != 1 {
+                return Err(StateError::KingCount(color, count));
+            }
+        }
+
+        for color in Color::iter() {
+            let pawns = self.get_bitboard_for(PieceType::Pawn, color);
+            if let Some(square) = Square::iter()
+                .filter(|square| square.rank() == 0 || square.rank() == 7)
+                .find(|&square| pawns.square_occupied(square))
+            {
+                return Err(StateError::PawnOnBackRank(square));
+            }
+        }
+
+        let castling_pieces = [
+            (CastlingRights::WHITE_KINGSIDE, Color::White, E1, H1),
+            (CastlingRights::WHITE_QUEENSIDE, Color::White, E1, A1),
+            (CastlingRights::BLACK_KINGSIDE, Color::Black, E8, H8),
+            (CastlingRights::BLACK_QUEENSIDE, Color::Black, E8, A8),
+        ];
+        for (rights, color, king, rook) in castling_pieces {
+            if self.castling_rights.contains(rights)
+                && !(self.piece_at(king) == Some((PieceType::King, color))
+                    && self.piece_at(rook) == Some((PieceType::Rook, color)))
+            {
+                return Err(StateError::CastlingRightsWithoutPieces(rights));
+            }
+        }
+
+        if let Some(square) = self.en_passant {
+            // The pawn that just moved belongs to the side not to move and passed
+            // over the en passant square from its initial rank.
+            let (ep_rank, pawn_rank, origin_rank) = match self.side_to_move {
+                Color::White => (5, 4, 6),
+                Color::Black => (2, 3, 1),
+            };
+            let on_file = |rank| Square::from_file_rank(square.file(), rank).unwrap();
+            let mover = self.side_to_move.opposite();
+            if square.rank() != ep_rank
+                || self.piece_at(square).is_some()
+                || self.piece_at(on_file(origin_rank)).is_some()
+                || self.piece_at(on_file(pawn_rank)) != Some((PieceType::Pawn, mover))
+            {
+                return Err(StateError::InvalidEnPassant(square));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn put_piece(&mut self, piece: PieceType, color: Color, square: Square) {
+        let index = self.get_index_for(piece, color);
+        self.bitboards[index].set_square(square);
+        self.hash ^= zobrist::piece_key(index, square);
+        if piece == PieceType::Pawn {
+            self.pawn_hash ^= zobrist::piece_key(index, square);
+        }
+        self.eval_terms.add(piece, color, square);
+        #[cfg(feature = "nnue")]
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.add(piece, color, square);
+        }
+    }
+
+    fn remove_piece(&mut self, piece: PieceType, color: Color, square: Square) {
+        let index = self.get_index_for(piece, color);
+        self.bitboards[index].clear_square(square);
+        self.hash ^= zobrist::piece_key(index, square);
+        if piece == PieceType::Pawn {
+            self.pawn_hash ^= zobrist::piece_key(index, square);
+        }
+        self.eval_terms.remove(piece, color, square);
+        #[cfg(feature = "nnue")]
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.remove(piece, color, square);
+        }
+    }
+
     fn get_index_for(&self, piece: PieceType, color: Color) -> usize {
         piece as usize + (PieceType::COUNT * color as usize)
     }
@@ -130,6 +469,81 @@ mod tests {
     use crate::board::state::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
     use Square::*;
 
+    #[test]
+    fn test_initial_game_state() {
+        let state = State::new();
+        assert_eq!(state.side_to_move(), White);
+        assert_eq!(state.castling_rights(), CastlingRights::ALL);
+        assert_eq!(state.en_passant(), None);
+        assert_eq!(state.halfmove_clock(), 0);
+        assert_eq!(state.fullmove_number(), 1);
+        assert_eq!(state.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_castling_rights() {
+        let mut rights = CastlingRights::NONE;
+        assert!(rights.is_empty());
+        rights.insert(CastlingRights::kingside(White));
+        rights.insert(CastlingRights::queenside(Black));
+        assert!(rights.contains(CastlingRights::WHITE_KINGSIDE));
+        assert!(rights.contains(CastlingRights::BLACK_QUEENSIDE));
+        assert!(!rights.contains(CastlingRights::WHITE_QUEENSIDE));
+        assert!(!rights.contains(CastlingRights::ALL));
+        rights.remove(CastlingRights::WHITE_KINGSIDE);
+        assert_eq!(rights, CastlingRights::BLACK_QUEENSIDE);
+        rights.remove(CastlingRights::ALL);
+        assert!(rights.is_empty());
+    }
+
+    #[test]
+    fn test_game_state_from_fen() {
+        let state = State::from_fen("4k3/8/8/8/3pP3/8/8/4K2R b K e3 0 23").unwrap();
+        assert_eq!(state.side_to_move(), Black);
+        assert_eq!(state.castling_rights(), CastlingRights::WHITE_KINGSIDE);
+        assert_eq!(state.en_passant(), Some(E3));
+        assert_eq!(state.halfmove_clock(), 0);
+        assert_eq!(state.fullmove_number(), 23);
+    }
+
+    #[test]
+    fn test_validate() {
+        let invalid = |state: State| state.validate().unwrap_err();
+
+        let mut state = State::new();
+        state.put_piece(Knight, Black, E2);
+        assert_eq!(invalid(state), StateError::OverlappingPieces(E2));
+
+        let mut state = State::new();
+        state.put_piece(King, White, E4);
+        assert_eq!(invalid(state), StateError::KingCount(White, 2));
+
+        let mut state = State::new();
+        state.bitboards[state.get_index_for(King, Black)] = Bitboard::from_bits(0);
+        assert_eq!(invalid(state), StateError::KingCount(Black, 0));
+
+        let mut state = State::new();
+        state.bitboards[state.get_index_for(Rook, Black)] = Bitboard::from_squares(&[A8]);
+        state.bitboards[state.get_index_for(Pawn, Black)] = Bitboard::from_squares(&[H8]);
+        assert_eq!(invalid(state), StateError::PawnOnBackRank(H8));
+
+        let mut state = State::new();
+        state.bitboards[state.get_index_for(Rook, Black)] = Bitboard::from_squares(&[A8]);
+        assert_eq!(
+            invalid(state),
+            StateError::CastlingRightsWithoutPieces(CastlingRights::BLACK_KINGSIDE)
+        );
+
+        let mut state = State::new();
+        state.en_passant = Some(E3);
+        assert_eq!(invalid(state), StateError::InvalidEnPassant(E3));
+
+        let mut state = State::new();
+        state.side_to_move = Black;
+        state.en_passant = Some(E3);
+        assert_eq!(invalid(state), StateError::InvalidEnPassant(E3));
+    }
+
     #[test]
     fn test_bitboard_from_square() {
         let bitboard_d7 = Bitboard::from_squares(&[E5]);
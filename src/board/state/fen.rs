@@ -0,0 +1,362 @@
+use crate::board::state::{CastlingRights, Color, PieceType, Square, State, StateError};
+use std::error::Error;
+use std::fmt;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TooManyFields(usize),
+    WrongRankCount(usize),
+    WrongRankLength { rank: u8, length: usize },
+    InvalidPiece(char),
+    InvalidSideToMove(String),
+    InvalidCastlingRights(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    IllegalPosition(StateError),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use FenError::*;
+        match self {
+            MissingField(field) => write!(f, "missing {field} field"),
+            TooManyFields(count) => write!(f, "expected at most 6 fields, found {count}"),
+            WrongRankCount(count) => write!(f, "expected 8 ranks, found {count}"),
+            WrongRankLength { rank, length } => {
+                write!(f, "rank {rank} describes {length} squares instead of 8")
+            }
+            InvalidPiece(c) => write!(f, "invalid piece character '{c}'"),
+            InvalidSideToMove(text) => write!(f, "invalid side to move '{text}'"),
+            InvalidCastlingRights(text) => write!(f, "invalid castling rights '{text}'"),
+            InvalidEnPassant(text) => write!(f, "invalid en passant square '{text}'"),
+            InvalidHalfmoveClock(text) => write!(f, "invalid halfmove clock '{text}'"),
+            InvalidFullmoveNumber(text) => write!(f, "invalid fullmove number '{text}'"),
+            IllegalPosition(error) => write!(f, "illegal position: {error}"),
+        }
+    }
+}
+
+impl Error for FenError {}
+
+impl State {
+    /// Parses a position in Forsyth-Edwards Notation. The two move clocks may be
+    /// omitted, as they are in EPD records, and then default to `0 1`.
+    pub fn from_fen(fen: &str) -> Result<State, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() > 6 {
+            return Err(FenError::TooManyFields(fields.len()));
+        }
+        let field = |index: usize, name: &'static str| {
+            fields
+                .get(index)
+                .copied()
+                .ok_or(FenError::MissingField(name))
+        };
+
+        let mut state = State::empty();
+        parse_placement(&mut state, field(0, "piece placement")?)?;
+        state.side_to_move = parse_side_to_move(field(1, "side to move")?)?;
+        state.castling_rights = parse_castling_rights(field(2, "castling rights")?)?;
+        state.en_passant = parse_en_passant(field(3, "en passant")?)?;
+        if let Some(&text) = fields.get(4) {
+            state.halfmove_clock = text
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(text.to_string()))?;
+        }
+        if let Some(&text) = fields.get(5) {
+            state.fullmove_number = text
+                .parse()
+                .ok()
+                .filter(|&number| number > 0)
+                .ok_or_else(|| FenError::InvalidFullmoveNumber(text.to_string()))?;
+        }
+        state.validate().map_err(FenError::IllegalPosition)?;
+        state.hash = state.compute_hash();
+        #[cfg(feature = "nnue")]
+        state.set_network(crate::eval::nnue::network());
+        Ok(state)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let square = Square::from_file_rank(file, rank).unwrap();
+                match self.piece_at(square) {
+                    Some((piece, color)) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece_to_char(piece, color));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.side_to_move {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        if self.castling_rights.is_empty() {
+            fen.push('-');
+        }
+        for (rights, c) in CASTLING_CHARS {
+            if self.castling_rights.contains(rights) {
+                fen.push(c);
+            }
+        }
+
+        match self.en_passant {
+            Some(square) => fen.push_str(&format!(" {square}")),
+            None => fen.push_str(" -"),
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+}
+
+const CASTLING_CHARS: [(CastlingRights, char); 4] = [
+    (CastlingRights::WHITE_KINGSIDE, 'K'),
+    (CastlingRights::WHITE_QUEENSIDE, 'Q'),
+    (CastlingRights::BLACK_KINGSIDE, 'k'),
+    (CastlingRights::BLACK_QUEENSIDE, 'q'),
+];
+
+fn parse_placement(state: &mut State, placement: &str) -> Result<(), FenError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+
+    // FEN lists the ranks from the eighth down to the first.
+    for (rank, description) in (0..8u8).rev().zip(ranks) {
+        let mut file = 0usize;
+        for c in description.chars() {
+            if let Some(skip) = c.to_digit(10).filter(|skip| (1..=8).contains(skip)) {
+                file += skip as usize;
+                continue;
+            }
+            let (piece, color) = piece_from_char(c).ok_or(FenError::InvalidPiece(c))?;
+            let square =
+                Square::from_file_rank(file as u8, rank).ok_or(FenError::WrongRankLength {
+                    rank: rank + 1,
+                    length: file + 1,
+                })?;
+            state.put_piece(piece, color, square);
+            file += 1;
+        }
+        if file != 8 {
+            return Err(FenError::WrongRankLength {
+                rank: rank + 1,
+                length: file,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn parse_side_to_move(text: &str) -> Result<Color, FenError> {
+    match text {
+        "w" => Ok(Color::White),
+        "b" => Ok(Color::Black),
+        _ => Err(FenError::InvalidSideToMove(text.to_string())),
+    }
+}
+
+fn parse_castling_rights(text: &str) -> Result<CastlingRights, FenError> {
+    let mut castling_rights = CastlingRights::NONE;
+    if text == "-" {
+        return Ok(castling_rights);
+    }
+    for c in text.chars() {
+        let (rights, _) = CASTLING_CHARS
+            .into_iter()
+            .find(|&(_, expected)| expected == c)
+            .ok_or_else(|| FenError::InvalidCastlingRights(text.to_string()))?;
+        if castling_rights.contains(rights) {
+            return Err(FenError::InvalidCastlingRights(text.to_string()));
+        }
+        castling_rights.insert(rights);
+    }
+    Ok(castling_rights)
+}
+
+fn parse_en_passant(text: &str) -> Result<Option<Square>, FenError> {
+    if text == "-" {
+        return Ok(None);
+    }
+    match text.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ (b'3' | b'6')] => {
+            Ok(Square::from_file_rank(file - b'a', rank - b'1'))
+        }
+        _ => Err(FenError::InvalidEnPassant(text.to_string())),
+    }
+}
+
+fn piece_from_char(c: char) -> Option<(PieceType, Color)> {
+    use PieceType::*;
+    let piece = match c.to_ascii_lowercase() {
+        'p' => Pawn,
+        'n' => Knight,
+        'b' => Bishop,
+        'r' => Rook,
+        'q' => Queen,
+        'k' => King,
+        _ => return None,
+    };
+    let color = if c.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some((piece, color))
+}
+
+fn piece_to_char(piece: PieceType, color: Color) -> char {
+    use PieceType::*;
+    let c = match piece {
+        Pawn => 'p',
+        Knight => 'n',
+        Bishop => 'b',
+        Rook => 'r',
+        Queen => 'q',
+        King => 'k',
+    };
+    match color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::state::Color::{Black, White};
+    use crate::board::state::PieceType::{King, Knight, Pawn, Rook};
+    use crate::board::state::Square::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn test_start_fen_matches_new() {
+        assert_eq!(State::from_fen(START_FEN).unwrap(), State::new());
+        assert_eq!(State::new().to_fen(), START_FEN);
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let fens = [
+            START_FEN,
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 17 42",
+        ];
+        for fen in fens {
+            assert_eq!(State::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_fen_fields() {
+        let state = State::from_fen("4k2r/8/8/3pP3/8/8/8/R3K3 w Qk d6 3 27").unwrap();
+        assert!(state.get_bitboard_for(Pawn, White).square_occupied(E5));
+        assert!(state.get_bitboard_for(Pawn, Black).square_occupied(D5));
+        assert!(state.get_bitboard_for(Rook, White).square_occupied(A1));
+        assert!(state.get_bitboard_for(King, Black).square_occupied(E8));
+        assert_eq!(state.piece_at(E1), Some((King, White)));
+        assert_eq!(state.piece_at(B1), None);
+        assert_eq!(state.side_to_move, White);
+        assert!(state
+            .castling_rights
+            .contains(CastlingRights::WHITE_QUEENSIDE));
+        assert!(state
+            .castling_rights
+            .contains(CastlingRights::BLACK_KINGSIDE));
+        assert!(!state
+            .castling_rights
+            .contains(CastlingRights::WHITE_KINGSIDE));
+        assert!(!state
+            .castling_rights
+            .contains(CastlingRights::BLACK_QUEENSIDE));
+        assert_eq!(state.en_passant, Some(D6));
+        assert_eq!(state.halfmove_clock, 3);
+        assert_eq!(state.fullmove_number, 27);
+    }
+
+    #[test]
+    fn test_fen_without_clocks() {
+        let state = State::from_fen("4k3/8/8/8/8/8/8/1N2K3 b - -").unwrap();
+        assert!(state.get_bitboard_for(Knight, White).square_occupied(B1));
+        assert_eq!(state.side_to_move, Black);
+        assert_eq!(state.to_fen(), "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1");
+    }
+
+    #[test]
+    fn test_fen_errors() {
+        use FenError::*;
+        let error = |fen: &str| State::from_fen(fen).unwrap_err();
+
+        assert_eq!(error(""), MissingField("piece placement"));
+        assert_eq!(error("8/8/8/8/8/8/8/8"), MissingField("side to move"));
+        assert_eq!(error("8/8/8/8/8/8/8/8 w"), MissingField("castling rights"));
+        assert_eq!(error("8/8/8/8/8/8/8/8 w -"), MissingField("en passant"));
+        assert_eq!(error("8/8/8/8/8/8/8/8 w - - 0 1 x"), TooManyFields(7));
+        assert_eq!(error("8/8/8/8/8/8/8 w - - 0 1"), WrongRankCount(7));
+        assert_eq!(
+            error("8/8/8/8/8/8/8/7 w - - 0 1"),
+            WrongRankLength { rank: 1, length: 7 }
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/4k4 w - - 0 1"),
+            WrongRankLength { rank: 1, length: 9 }
+        );
+        assert_eq!(
+            error("ppppppppp/8/8/8/8/8/8/8 w - - 0 1"),
+            WrongRankLength { rank: 8, length: 9 }
+        );
+        assert_eq!(error("8/8/8/8/8/8/8/7x w - - 0 1"), InvalidPiece('x'));
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 x - - 0 1"),
+            InvalidSideToMove("x".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w KK - 0 1"),
+            InvalidCastlingRights("KK".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w KX - 0 1"),
+            InvalidCastlingRights("KX".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w - e4 0 1"),
+            InvalidEnPassant("e4".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w - - x 1"),
+            InvalidHalfmoveClock("x".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w - - 0 0"),
+            InvalidFullmoveNumber("0".into())
+        );
+    }
+}
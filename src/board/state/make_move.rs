@@ -0,0 +1,309 @@
+use crate::board::bitboard::en_passant_target;
+use crate::board::moves::Move;
+use crate::board::state::zobrist::{black_to_move_key, castling_key, en_passant_key};
+use crate::board::state::{CastlingRights, Color, PieceType, Square, State};
+use crate::eval::terms::EvalTerms;
+
+/// What `make_move` cannot recompute when the move is taken back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Undo {
+    chess_move: Move,
+    captured: Option<PieceType>,
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u16,
+    fullmove_number: u16,
+}
+
+impl State {
+    /// Plays `chess_move`, which must be legal in this position, and remembers
+    /// enough to take it back with [`State::unmake_move`].
+    pub fn make_move(&mut self, chess_move: Move) {
+        let color = self.side_to_move;
+        let from = chess_move.from();
+        let to = chess_move.to();
+        let (piece, _) = self
+            .piece_at(from)
+            .expect("a move starts on a square holding a piece of the side to move");
+
+        let captured_square = if chess_move.is_en_passant() {
+            Square::from_file_rank(to.file(), from.rank()).unwrap()
+        } else {
+            to
+        };
+        let captured = if chess_move.is_capture() {
+            self.piece_at(captured_square).map(|(piece, _)| piece)
+        } else {
+            None
+        };
+
+        self.history.push(Undo {
+            chess_move,
+            captured,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        });
+
+        if let Some(captured) = captured {
+            self.remove_piece(captured, color.opposite(), captured_square);
+        }
+        self.remove_piece(piece, color, from);
+        self.put_piece(chess_move.promotion_piece().unwrap_or(piece), color, to);
+        if chess_move.is_castle() {
+            let (rook_from, rook_to) = castling_rook_squares(to);
+            self.remove_piece(PieceType::Rook, color, rook_from);
+            self.put_piece(PieceType::Rook, color, rook_to);
+        }
+
+        self.hash ^= castling_key(self.castling_rights) ^ en_passant_key(self.en_passant);
+        self.castling_rights.remove(castling_rights_lost(from));
+        self.castling_rights.remove(castling_rights_lost(to));
+        self.en_passant = if chess_move.is_double_push() {
+            en_passant_target(color, from, to)
+        } else {
+            None
+        };
+        self.hash ^= castling_key(self.castling_rights) ^ en_passant_key(self.en_passant);
+        if piece == PieceType::Pawn || captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            // The clocks come from the FEN and may already be at their limit.
+            self.halfmove_clock = self.halfmove_clock.saturating_add(1);
+        }
+        if color == Color::Black {
+            self.fullmove_number = self.fullmove_number.saturating_add(1);
+        }
+        self.side_to_move = color.opposite();
+        self.hash ^= black_to_move_key();
+        debug_assert_eq!(self.hash, self.compute_hash());
+        debug_assert_eq!(self.pawn_hash, self.compute_pawn_hash());
+        debug_assert_eq!(self.eval_terms, EvalTerms::compute(self));
+    }
+
+    /// Takes back the last move played with [`State::make_move`].
+    pub fn unmake_move(&mut self) {
+        let undo = self
+            .history
+            .pop()
+            .expect("unmake_move is only called after make_move");
+        let chess_move = undo.chess_move;
+        let color = self.side_to_move.opposite();
+        let from = chess_move.from();
+        let to = chess_move.to();
+
+        let (moved, _) = self.piece_at(to).expect("the moved piece is on its target");
+        let piece = if chess_move.is_promotion() {
+            PieceType::Pawn
+        } else {
+            moved
+        };
+        self.remove_piece(moved, color, to);
+        self.put_piece(piece, color, from);
+        if chess_move.is_castle() {
+            let (rook_from, rook_to) = castling_rook_squares(to);
+            self.remove_piece(PieceType::Rook, color, rook_to);
+            self.put_piece(PieceType::Rook, color, rook_from);
+        }
+        if let Some(captured) = undo.captured {
+            let captured_square = if chess_move.is_en_passant() {
+                Square::from_file_rank(to.file(), from.rank()).unwrap()
+            } else {
+                to
+            };
+            self.put_piece(captured, color.opposite(), captured_square);
+        }
+
+        self.hash ^= castling_key(self.castling_rights) ^ en_passant_key(self.en_passant);
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.hash ^= castling_key(self.castling_rights) ^ en_passant_key(self.en_passant);
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.side_to_move = color;
+        self.hash ^= black_to_move_key();
+        debug_assert_eq!(self.hash, self.compute_hash());
+        debug_assert_eq!(self.pawn_hash, self.compute_pawn_hash());
+        debug_assert_eq!(self.eval_terms, EvalTerms::compute(self));
+    }
+
+    /// The moves played since the position was set up, oldest first.
+    pub fn moves_played(&self) -> impl DoubleEndedIterator<Item = Move> + '_ {
+        self.history.iter().map(|undo| undo.chess_move)
+    }
+}
+
+/// Where the rook starts and ends when the king castles to `king_to`.
+fn castling_rook_squares(king_to: Square) -> (Square, Square) {
+    use Square::*;
+    match king_to {
+        G1 => (H1, F1),
+        C1 => (A1, D1),
+        G8 => (H8, F8),
+        C8 => (A8, D8),
+        _ => unreachable!("castling moves the king to the c- or g-file"),
+    }
+}
+
+/// The castling rights that disappear when a piece moves from or to `square`.
+fn castling_rights_lost(square: Square) -> CastlingRights {
+    use Square::*;
+    match square {
+        E1 => CastlingRights::WHITE,
+        H1 => CastlingRights::WHITE_KINGSIDE,
+        A1 => CastlingRights::WHITE_QUEENSIDE,
+        E8 => CastlingRights::BLACK,
+        H8 => CastlingRights::BLACK_KINGSIDE,
+        A8 => CastlingRights::BLACK_QUEENSIDE,
+        _ => CastlingRights::NONE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    fn play(state: &mut State, text: &str) {
+        state.make_move(Move::from_uci(text, state).unwrap());
+    }
+
+    #[test]
+    fn test_make_quiet_and_double_push() {
+        let mut state = State::new();
+        play(&mut state, "e2e4");
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        play(&mut state, "g8f6");
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"
+        );
+        state.unmake_move();
+        state.unmake_move();
+        assert_eq!(state, State::new());
+    }
+
+    #[test]
+    fn test_make_castling() {
+        let mut state = State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 3 10").unwrap();
+        play(&mut state, "e1g1");
+        assert_eq!(state.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 4 10");
+        play(&mut state, "e8c8");
+        assert_eq!(state.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 5 11");
+        state.unmake_move();
+        assert_eq!(state.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 4 10");
+    }
+
+    #[test]
+    fn test_make_en_passant() {
+        let mut state = State::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+        play(&mut state, "e5d6");
+        assert_eq!(state.to_fen(), "4k3/8/3P4/8/8/8/8/4K3 b - - 0 2");
+        state.unmake_move();
+        assert_eq!(state.to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+    }
+
+    #[test]
+    fn test_make_promotion_and_rook_capture() {
+        let mut state = State::from_fen("r3k2r/1P6/8/8/8/8/8/4K3 w kq - 0 1").unwrap();
+        play(&mut state, "b7a8q");
+        assert_eq!(state.to_fen(), "Q3k2r/8/8/8/8/8/8/4K3 b k - 0 1");
+        assert_eq!(
+            state.history.last().unwrap().captured,
+            Some(PieceType::Rook)
+        );
+        state.unmake_move();
+        assert_eq!(state.to_fen(), "r3k2r/1P6/8/8/8/8/8/4K3 w kq - 0 1");
+
+        play(&mut state, "b7b8n");
+        assert_eq!(state.to_fen(), "rN2k2r/8/8/8/8/8/8/4K3 b kq - 0 1");
+    }
+
+    #[test]
+    fn test_moving_king_or_rook_loses_castling_rights() {
+        let mut state = State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        play(&mut state, "h1h5");
+        assert!(!state
+            .castling_rights()
+            .contains(CastlingRights::WHITE_KINGSIDE));
+        assert!(state
+            .castling_rights()
+            .contains(CastlingRights::WHITE_QUEENSIDE));
+        play(&mut state, "e8d8");
+        assert!(!state
+            .castling_rights()
+            .contains(CastlingRights::BLACK_KINGSIDE));
+        assert!(!state
+            .castling_rights()
+            .contains(CastlingRights::BLACK_QUEENSIDE));
+    }
+
+    #[test]
+    fn test_clocks_stop_at_their_limit() {
+        for (fen, text, clocks) in [
+            ("4k3/8/8/8/8/8/8/4K2R w - - 65535 1", "h1h2", " 65535 1"),
+            ("4k3/8/8/8/8/8/8/4K2R b - - 0 65535", "e8d8", " 1 65535"),
+        ] {
+            let mut state = State::from_fen(fen).unwrap();
+            play(&mut state, text);
+            assert!(state.to_fen().ends_with(clocks), "{fen}");
+            state.unmake_move();
+            assert_eq!(state, State::from_fen(fen).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_moves_played() {
+        let mut state = State::new();
+        play(&mut state, "e2e4");
+        play(&mut state, "e7e5");
+        let played: Vec<String> = state.moves_played().map(|m| m.to_string()).collect();
+        assert_eq!(played, ["e2e4", "e7e5"]);
+    }
+
+    #[test]
+    fn test_random_games_unwind_to_original() {
+        // xorshift64, so failures are reproducible.
+        let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut random = move |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+
+        for fen in POSITIONS {
+            for _ in 0..20 {
+                let original = State::from_fen(fen).unwrap();
+                let mut state = original.clone();
+                let mut snapshots = Vec::new();
+                for _ in 0..60 {
+                    let moves = state.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    snapshots.push(state.clone());
+                    state.make_move(moves.as_slice()[random(moves.len())]);
+                    assert_eq!(state.validate(), Ok(()), "{}", state.to_fen());
+                }
+                while let Some(snapshot) = snapshots.pop() {
+                    state.unmake_move();
+                    assert_eq!(state, snapshot);
+                }
+                assert_eq!(state, original);
+            }
+        }
+    }
+}
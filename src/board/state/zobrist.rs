@@ -0,0 +1,216 @@
+use crate::board::state::{CastlingRights, Color, PieceType, Square, State};
+use strum::{EnumCount, IntoEnumIterator};
+
+// Zobrist hashing: every feature of a position (a piece of some kind on some
+// square, the side to move, the castling rights, the en passant file) has a
+// random 64-bit key, and the hash of a position is the XOR of the keys of its
+// features. Making a move only toggles the keys of the features it changes.
+// The keys come from a fixed seed, so hashes are the same in every run.
+
+struct Keys {
+    pieces: [[u64; 64]; PieceType::COUNT * Color::COUNT],
+    black_to_move: u64,
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+}
+
+const SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+static KEYS: Keys = generate_keys();
+
+const fn generate_keys() -> Keys {
+    // xorshift64*
+    const fn next(state: &mut u64) -> u64 {
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    let mut state = SEED;
+    let mut keys = Keys {
+        pieces: [[0; 64]; PieceType::COUNT * Color::COUNT],
+        black_to_move: 0,
+        castling: [0; 16],
+        en_passant: [0; 8],
+    };
+    let mut piece = 0;
+    while piece < keys.pieces.len() {
+        let mut square = 0;
+        while square < 64 {
+            keys.pieces[piece][square] = next(&mut state);
+            square += 1;
+        }
+        piece += 1;
+    }
+    keys.black_to_move = next(&mut state);
+    // Castling rights with no right at all hash to nothing.
+    let mut rights = 1;
+    while rights < 16 {
+        keys.castling[rights] = next(&mut state);
+        rights += 1;
+    }
+    let mut file = 0;
+    while file < 8 {
+        keys.en_passant[file] = next(&mut state);
+        file += 1;
+    }
+    keys
+}
+
+pub fn piece_key(index: usize, square: Square) -> u64 {
+    KEYS.pieces[index][square as usize]
+}
+
+pub fn black_to_move_key() -> u64 {
+    KEYS.black_to_move
+}
+
+pub fn castling_key(rights: CastlingRights) -> u64 {
+    KEYS.castling[rights.bits as usize]
+}
+
+pub fn en_passant_key(en_passant: Option<Square>) -> u64 {
+    en_passant.map_or(0, |square| KEYS.en_passant[square.file() as usize])
+}
+
+impl State {
+    /// The Zobrist hash of the position. Positions that are the same for the
+    /// rules of chess hash the same, whatever moves led to them.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// The Zobrist hash of the pawns alone, both colors. Pawn structure
+    /// evaluation is cached under it, as pawns move far less often than the
+    /// other pieces.
+    pub fn pawn_hash(&self) -> u64 {
+        self.pawn_hash
+    }
+
+    /// Computes the hash from scratch, as the incremental updates of
+    /// `make_move` and `unmake_move` must keep it.
+    pub(super) fn compute_hash(&self) -> u64 {
+        let mut hash = castling_key(self.castling_rights) ^ en_passant_key(self.en_passant);
+        if self.side_to_move == Color::Black {
+            hash ^= black_to_move_key();
+        }
+        for piece in PieceType::iter() {
+            for color in Color::iter() {
+                let index = self.get_index_for(piece, color);
+                for square in self.bitboards[index].squares() {
+                    hash ^= piece_key(index, square);
+                }
+            }
+        }
+        hash
+    }
+
+    /// Computes the pawn hash from scratch.
+    pub(super) fn compute_pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+        for color in Color::iter() {
+            let index = self.get_index_for(PieceType::Pawn, color);
+            for square in self.bitboards[index].squares() {
+                hash ^= piece_key(index, square);
+            }
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::moves::Move;
+    use crate::board::state::START_FEN;
+
+    fn play(moves: &[&str]) -> State {
+        let mut state = State::new();
+        for text in moves {
+            state.make_move(Move::from_uci(text, &state).unwrap());
+        }
+        state
+    }
+
+    #[test]
+    fn test_hash_is_reproducible() {
+        assert_eq!(State::new().hash(), 0xC3F5_EEA9_4DDB_7575);
+        assert_eq!(
+            State::from_fen(START_FEN).unwrap().hash(),
+            State::new().hash()
+        );
+    }
+
+    #[test]
+    fn test_transpositions_hash_the_same() {
+        let first = play(&["g1f3", "b8c6", "b1c3"]);
+        let second = play(&["b1c3", "b8c6", "g1f3"]);
+        assert_eq!(first.hash(), second.hash());
+        assert_eq!(
+            first.hash(),
+            State::from_fen(&first.to_fen()).unwrap().hash()
+        );
+
+        // Knights out and back again only changes the move clocks.
+        let back = play(&["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(back.hash(), State::new().hash());
+    }
+
+    #[test]
+    fn test_hash_covers_game_state() {
+        let hash = |fen: &str| State::from_fen(fen).unwrap().hash();
+        let base = hash("r3k2r/8/8/8/4p3/8/3P4/R3K2R w KQkq - 0 1");
+        assert_ne!(base, hash("r3k2r/8/8/8/4p3/8/3P4/R3K2R b KQkq - 0 1"));
+        assert_ne!(base, hash("r3k2r/8/8/8/4p3/8/3P4/R3K2R w Kkq - 0 1"));
+        assert_ne!(
+            hash("r3k2r/8/8/8/3Pp3/8/8/R3K2R b KQkq d3 0 1"),
+            hash("r3k2r/8/8/8/3Pp3/8/8/R3K2R b KQkq - 0 1")
+        );
+
+        // Losing castling rights by moving the king and back is not a
+        // transposition.
+        let mut state = State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for text in ["e1f1", "e8f8", "f1e1", "f8e8"] {
+            state.make_move(Move::from_uci(text, &state).unwrap());
+        }
+        assert_ne!(state.hash(), hash("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        assert_eq!(
+            state.hash(),
+            State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w - - 4 3")
+                .unwrap()
+                .hash()
+        );
+    }
+
+    #[test]
+    fn test_unmake_restores_hash() {
+        let mut state = play(&["e2e4", "d7d5", "e4d5", "g8f6", "f1b5", "c7c6"]);
+        let before = state.hash();
+        for text in ["d5c6", "d8d2", "b1d2", "c8g4"] {
+            state.make_move(Move::from_uci(text, &state).unwrap());
+        }
+        for _ in 0..4 {
+            state.unmake_move();
+        }
+        assert_eq!(state.hash(), before);
+    }
+
+    #[test]
+    fn test_pawn_hash_only_sees_pawns() {
+        let moved_knights = play(&["g1f3", "g8f6"]);
+        assert_eq!(moved_knights.pawn_hash(), State::new().pawn_hash());
+        assert_ne!(moved_knights.hash(), State::new().hash());
+
+        let mut state = play(&["e2e4", "d7d5"]);
+        assert_ne!(state.pawn_hash(), State::new().pawn_hash());
+        let before = state.pawn_hash();
+        state.make_move(Move::from_uci("e4d5", &state).unwrap());
+        assert_eq!(
+            state.pawn_hash(),
+            State::from_fen(&state.to_fen()).unwrap().pawn_hash()
+        );
+        state.unmake_move();
+        assert_eq!(state.pawn_hash(), before);
+    }
+}
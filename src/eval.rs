@@ -0,0 +1,209 @@
+use crate::board::state::{Color, State};
+use pawns::{PawnEval, PawnTable};
+use terms::EvalTerms;
+
+pub mod activity;
+pub mod king_safety;
+#[cfg(feature = "nnue")]
+pub mod nnue;
+pub mod pawns;
+mod tables;
+pub mod terms;
+pub mod weights;
+
+pub use tables::WEIGHTS;
+pub use weights::{Weights, S};
+
+/// How much each piece type counts towards the game phase. Knights and bishops
+/// count one, rooks two and queens four, so the starting position has
+/// `MAX_PHASE` and a position with only kings and pawns zero.
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+/// The static evaluation of `state` in centipawns, from the point of view of
+/// the side to move. With a network loaded, it evaluates instead of the
+/// hand-crafted terms.
+pub fn evaluate(state: &State) -> i32 {
+    if let Some(score) = evaluate_network(state) {
+        return score;
+    }
+    score(
+        state,
+        &WEIGHTS,
+        &state.eval_terms(),
+        &pawns::evaluate(state, &WEIGHTS),
+    )
+}
+
+/// The hand-crafted part of [`evaluate`] with other weights than the built-in
+/// ones. Nothing is taken from the sums `State` keeps, as they use the
+/// built-in weights.
+pub fn evaluate_with(state: &State, weights: &Weights) -> i32 {
+    score(
+        state,
+        weights,
+        &EvalTerms::compute_with(state, weights),
+        &pawns::evaluate(state, weights),
+    )
+}
+
+/// Evaluates positions as [`evaluate`] does, keeping the pawn structure
+/// evaluations in a [`PawnTable`] to reuse them. One per search thread.
+#[derive(Default)]
+pub struct Evaluator {
+    pawns: PawnTable,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn evaluate(&mut self, state: &State) -> i32 {
+        if let Some(score) = evaluate_network(state) {
+            return score;
+        }
+        let pawns = self.pawns.probe(state, &WEIGHTS);
+        score(state, &WEIGHTS, &state.eval_terms(), &pawns)
+    }
+}
+
+#[cfg(feature = "nnue")]
+fn evaluate_network(state: &State) -> Option<i32> {
+    state
+        .accumulator()
+        .map(|accumulator| accumulator.evaluate(state.side_to_move()))
+}
+
+#[cfg(not(feature = "nnue"))]
+fn evaluate_network(_state: &State) -> Option<i32> {
+    None
+}
+
+fn score(state: &State, weights: &Weights, terms: &EvalTerms, pawns: &PawnEval) -> i32 {
+    let attacks = state.attack_maps();
+    let total = terms.material
+        + terms.psqt
+        + pawns.score
+        + pawns::free_passed_pawns(state, weights, pawns.passed)
+        + king_safety::evaluate(state, weights, &attacks)
+        + activity::evaluate(state, weights, &attacks);
+    let white = taper(total, terms.phase);
+    match state.side_to_move() {
+        Color::White => white,
+        Color::Black => -white,
+    }
+}
+
+/// Blends the midgame and endgame values of `score`: all midgame at
+/// `MAX_PHASE`, all endgame at zero. Promotions can push the phase above
+/// `MAX_PHASE`; it is capped there.
+pub fn taper(score: S, phase: i32) -> i32 {
+    let phase = phase.min(MAX_PHASE);
+    (score.mg() * phase + score.eg() * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::moves::Move;
+    use crate::board::state::START_FEN;
+
+    /// The same position with the colors swapped and the board flipped.
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |text: &str| {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect::<String>()
+        };
+        let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match fields[3] {
+            "-" => "-".to_string(),
+            square => {
+                let rank = 9 - square[1..].parse::<u32>().unwrap();
+                format!("{}{rank}", &square[..1])
+            }
+        };
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        castling.sort_by_key(|&right| "KQkq".find(right));
+        let castling: String = castling.into_iter().collect();
+        format!("{} {side} {castling} {en_passant} 0 1", placement.join("/"))
+    }
+
+    fn eval(fen: &str) -> i32 {
+        evaluate(&State::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn test_start_position_is_balanced() {
+        assert_eq!(eval(START_FEN), 0);
+    }
+
+    #[test]
+    fn test_evaluation_is_symmetric() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            assert_eq!(eval(fen), eval(&mirror(fen)), "{fen}");
+            let other_side = fen.replacen(" w ", " b ", 1);
+            assert_eq!(eval(fen), -eval(&other_side), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_material_counts() {
+        // White is a knight up.
+        let score = eval("rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(score > 250, "{score}");
+        let score = eval("rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+        assert!(score < -250, "{score}");
+    }
+
+    #[test]
+    fn test_incremental_terms_match_weights() {
+        let mut state = State::new();
+        for text in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5"] {
+            state.make_move(Move::from_uci(text, &state).unwrap());
+            assert_eq!(evaluate(&state), evaluate_with(&state, &WEIGHTS));
+        }
+    }
+
+    #[test]
+    fn test_evaluator_matches_evaluate() {
+        let mut evaluator = Evaluator::new();
+        let mut state = State::new();
+        for text in ["e2e4", "d7d5", "e4d5", "c7c6", "d5c6", "b8c6", "g1f3"] {
+            state.make_move(Move::from_uci(text, &state).unwrap());
+            assert_eq!(evaluator.evaluate(&state), evaluate(&state), "{text}");
+        }
+        for _ in 0..3 {
+            state.unmake_move();
+            assert_eq!(evaluator.evaluate(&state), evaluate(&state));
+        }
+    }
+
+    #[test]
+    fn test_taper() {
+        assert_eq!(taper(S(100, 20), MAX_PHASE), 100);
+        assert_eq!(taper(S(100, 20), 0), 20);
+        assert_eq!(taper(S(100, 20), MAX_PHASE / 2), 60);
+        assert_eq!(taper(S(100, 20), MAX_PHASE + 4), 100);
+    }
+
+    #[test]
+    fn test_king_prefers_the_center_in_the_endgame() {
+        let center = eval("8/8/8/4k3/8/8/8/4K3 b - - 0 1");
+        let corner = eval("7k/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(center > corner);
+    }
+}
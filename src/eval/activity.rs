@@ -0,0 +1,219 @@
+use crate::board::attacks::AttackMaps;
+use crate::board::bitboard::Bitboard;
+use crate::board::state::{CastlingRights, Color, PieceType, Square, State};
+use crate::eval::pawns::{adjacent_files, file_mask, ranks_ahead, relative_rank, RANK_1};
+use crate::eval::weights::{Weights, S};
+
+/// Piece activity, White's minus Black's: mobility, the bishop pair, rook
+/// files and ranks, knight outposts and trapped pieces.
+pub fn evaluate(state: &State, weights: &Weights, attacks: &AttackMaps) -> S {
+    mobility(state, weights, attacks, Color::White) + pieces(state, weights, attacks, Color::White)
+        - mobility(state, weights, attacks, Color::Black)
+        - pieces(state, weights, attacks, Color::Black)
+}
+
+fn mobility(state: &State, weights: &Weights, attacks: &AttackMaps, color: Color) -> S {
+    let safe = safe_squares(state, attacks, color);
+    let mut score = S::ZERO;
+    for (piece, table) in [
+        (PieceType::Knight, &weights.knight_mobility[..]),
+        (PieceType::Bishop, &weights.bishop_mobility[..]),
+        (PieceType::Rook, &weights.rook_mobility[..]),
+        (PieceType::Queen, &weights.queen_mobility[..]),
+    ] {
+        for square in state.get_bitboard_for(piece, color).squares() {
+            let reach = attacks.from_square(square) & safe;
+            score += table[reach.count() as usize];
+        }
+    }
+    score
+}
+
+/// The squares a `color` piece may go to without being taken by a pawn.
+fn safe_squares(state: &State, attacks: &AttackMaps, color: Color) -> Bitboard {
+    !(state.occupied_by(color) | attacks.by_piece(PieceType::Pawn, color.opposite()))
+}
+
+fn pieces(state: &State, weights: &Weights, attacks: &AttackMaps, color: Color) -> S {
+    let enemy = color.opposite();
+    let own_pawns = state.get_bitboard_for(PieceType::Pawn, color);
+    let enemy_pawns = state.get_bitboard_for(PieceType::Pawn, enemy);
+    let mut score = S::ZERO;
+
+    let bishops = state.get_bitboard_for(PieceType::Bishop, color);
+    if bishops.count() >= 2 {
+        score += weights.bishop_pair;
+    }
+    for square in bishops.squares() {
+        // A bishop on a7 is shut in by a pawn on b6 and lost once ...c6 or
+        // ...b5 cuts off its retreat.
+        let corner_file = match square.file() {
+            0 => Some(1),
+            7 => Some(6),
+            _ => None,
+        };
+        if let Some(file) = corner_file {
+            if relative_rank(color, square) == 6
+                && enemy_pawns.square_occupied(relative_square(color, file, 5))
+            {
+                score += weights.trapped_bishop;
+            }
+        }
+    }
+
+    for square in state.get_bitboard_for(PieceType::Knight, color).squares() {
+        let rank = relative_rank(color, square);
+        let attackable = adjacent_files(square.file()) & ranks_ahead(color, square.rank());
+        if (3..=5).contains(&rank)
+            && attacks
+                .by_piece(PieceType::Pawn, color)
+                .square_occupied(square)
+            && enemy_pawns.get_bits() & attackable == 0
+        {
+            score += weights.knight_outpost;
+        }
+    }
+
+    let king = state.king_square(color);
+    let enemy_king = state.king_square(enemy);
+    let safe = safe_squares(state, attacks, color);
+    let seventh = RANK_1 << relative_square(color, 0, 6).rank();
+    let kingside = king.file() >= 4;
+    let rights = if kingside {
+        CastlingRights::kingside(color)
+    } else {
+        CastlingRights::queenside(color)
+    };
+    for square in state.get_bitboard_for(PieceType::Rook, color).squares() {
+        let file = file_mask(square.file());
+        if own_pawns.get_bits() & file == 0 {
+            score += if enemy_pawns.get_bits() & file == 0 {
+                weights.rook_open_file
+            } else {
+                weights.rook_semi_open_file
+            };
+        }
+
+        if relative_rank(color, square) == 6
+            && (relative_rank(color, enemy_king) == 7 || enemy_pawns.get_bits() & seventh != 0)
+        {
+            score += weights.rook_on_seventh;
+        }
+
+        // A rook in the corner behind a king that stepped aside instead of
+        // castling.
+        let reach = attacks.from_square(square) & safe;
+        if relative_rank(color, square) == 0
+            && relative_rank(color, king) == 0
+            && (square.file() > king.file()) == kingside
+            && reach.count() <= 3
+            && !state.castling_rights().contains(rights)
+        {
+            score += weights.trapped_rook;
+        }
+    }
+    score
+}
+
+/// The square on `file` and `rank` counted from `color`'s side.
+fn relative_square(color: Color, file: u8, rank: u8) -> Square {
+    let rank = match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    };
+    Square::from_file_rank(file, rank).expect("files and ranks are below 8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::WEIGHTS;
+
+    fn pieces_of(fen: &str, color: Color) -> S {
+        let state = State::from_fen(fen).unwrap();
+        pieces(&state, &WEIGHTS, &state.attack_maps(), color)
+    }
+
+    fn mobility_of(fen: &str, color: Color) -> S {
+        let state = State::from_fen(fen).unwrap();
+        mobility(&state, &WEIGHTS, &state.attack_maps(), color)
+    }
+
+    #[test]
+    fn test_mobility_counts_safe_squares() {
+        let w = &WEIGHTS;
+        // The knight on a1 reaches b3 and c2.
+        assert_eq!(
+            mobility_of("4k3/8/8/8/8/8/8/N3K3 w - - 0 1", Color::White),
+            w.knight_mobility[2]
+        );
+        // c2 is held by a pawn of its own, b3 attacked by a black pawn.
+        assert_eq!(
+            mobility_of("4k3/8/8/8/p7/8/2P5/N3K3 w - - 0 1", Color::White),
+            w.knight_mobility[0]
+        );
+        // The rook reaches 13 squares up to its king, the queen 26 past the
+        // rook and a bishop in the center 13.
+        assert_eq!(
+            mobility_of("7k/8/8/8/3Q4/8/8/R6K w - - 0 1", Color::White),
+            w.rook_mobility[13] + w.queen_mobility[26]
+        );
+        assert_eq!(
+            mobility_of("7k/8/8/8/3B4/8/8/7K w - - 0 1", Color::White),
+            w.bishop_mobility[13]
+        );
+    }
+
+    #[test]
+    fn test_bishop_pair_and_trapped_bishop() {
+        let w = &WEIGHTS;
+        assert_eq!(
+            pieces_of("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", Color::White),
+            w.bishop_pair
+        );
+        assert_eq!(
+            pieces_of("4k3/B1p5/1p6/8/8/8/8/4K3 w - - 0 1", Color::White),
+            w.trapped_bishop
+        );
+        assert_eq!(
+            pieces_of("4k3/8/8/8/8/6P1/7b/4K3 b - - 0 1", Color::Black),
+            w.trapped_bishop
+        );
+    }
+
+    #[test]
+    fn test_rooks() {
+        let w = &WEIGHTS;
+        // Open a-file, semi-open d-file, closed h-file.
+        assert_eq!(
+            pieces_of("4k3/3p3p/8/8/8/8/7P/R2R2K1 w - - 0 1", Color::White),
+            w.rook_open_file + w.rook_semi_open_file
+        );
+        // On the seventh with the black king on the eighth.
+        assert_eq!(
+            pieces_of("4k3/1R5p/8/8/8/8/7P/6K1 w - - 0 1", Color::White),
+            w.rook_open_file + w.rook_on_seventh
+        );
+        // Shut in by the king on f1, but not while the king may still castle.
+        let trapped = "4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1";
+        assert_eq!(pieces_of(trapped, Color::White), w.trapped_rook);
+        assert_eq!(
+            pieces_of("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1", Color::White),
+            S::ZERO
+        );
+    }
+
+    #[test]
+    fn test_knight_outpost() {
+        let w = &WEIGHTS;
+        // The e4 pawn guards the knight on d5 and no black pawn can chase it.
+        assert_eq!(
+            pieces_of("4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1", Color::White),
+            w.knight_outpost
+        );
+        assert_eq!(
+            pieces_of("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1", Color::White),
+            S::ZERO
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::board::attacks::AttackMaps;
+use crate::board::bitboard::{Bitboard, Bits};
+use crate::board::state::{Color, PieceType, State};
+use crate::eval::pawns::{file_mask, ranks_ahead, relative_rank};
+use crate::eval::weights::{Weights, S};
+use strum::IntoEnumIterator;
+
+// Past this many attack units the king is lost anyway, and the penalty stops
+// growing.
+const MAX_ATTACK_UNITS: i32 = 40;
+
+/// King safety, White's minus Black's: the enemy attacks on the squares
+/// around each king and the pawns on the files next to it.
+pub fn evaluate(state: &State, weights: &Weights, attacks: &AttackMaps) -> S {
+    king_safety(state, weights, attacks, Color::White)
+        - king_safety(state, weights, attacks, Color::Black)
+}
+
+fn king_safety(state: &State, weights: &Weights, attacks: &AttackMaps, color: Color) -> S {
+    let king = state.king_square(color);
+    let enemy = color.opposite();
+
+    // The attack units grow linearly with the attacks and the penalty with
+    // their square, so that one attacker matters little and several a lot.
+    let units = attack_units(state, weights, attacks, color);
+    let mut score = weights.king_danger * (units * units) / 64;
+
+    let own_pawns = state.get_bitboard_for(PieceType::Pawn, color).get_bits();
+    let enemy_pawns = state.get_bitboard_for(PieceType::Pawn, enemy).get_bits();
+    let ahead = ranks_ahead(color, king.rank());
+    let king_rank = relative_rank(color, king);
+    // How many ranks in front of the king the closest of `pawns` is.
+    let closest = |pawns: Bits| {
+        Bitboard::from_bits(pawns & ahead)
+            .squares()
+            .map(|square| relative_rank(color, square) - king_rank)
+            .min()
+    };
+    for file in king.file().saturating_sub(1)..=(king.file() + 1).min(7) {
+        let file = file_mask(file);
+        if own_pawns & file == 0 {
+            score += if enemy_pawns & file == 0 {
+                weights.king_open_file
+            } else {
+                weights.king_semi_open_file
+            };
+        }
+        match closest(own_pawns & file) {
+            Some(1) => score += weights.pawn_shield[0],
+            Some(2) => score += weights.pawn_shield[1],
+            _ => {}
+        }
+        if let Some(distance @ 1..=4) = closest(enemy_pawns & file) {
+            score += weights.pawn_storm[distance - 1];
+        }
+    }
+    score
+}
+
+/// The attack units of the enemy pieces on the zone around the `color` king,
+/// the king's square and its neighbors. Each piece counts every zone square
+/// it attacks, so pieces that hit the same square all count.
+fn attack_units(state: &State, weights: &Weights, attacks: &AttackMaps, color: Color) -> i32 {
+    let king = state.king_square(color);
+    let enemy = color.opposite();
+    let zone = attacks.from_square(king) | Bitboard::from_squares(&[king]);
+    PieceType::iter()
+        .map(|piece| (piece, weights.king_attack_weight[piece as usize]))
+        .filter(|&(_, weight)| weight != 0)
+        .map(|(piece, weight)| {
+            let attacked = state
+                .get_bitboard_for(piece, enemy)
+                .squares()
+                .map(|square| (attacks.from_square(square) & zone).count() as i32)
+                .sum::<i32>();
+            weight * attacked
+        })
+        .sum::<i32>()
+        .min(MAX_ATTACK_UNITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::WEIGHTS;
+
+    fn king_safety_of(fen: &str, color: Color) -> S {
+        let state = State::from_fen(fen).unwrap();
+        king_safety(&state, &WEIGHTS, &state.attack_maps(), color)
+    }
+
+    #[test]
+    fn test_pawn_shield_and_open_files() {
+        let w = &WEIGHTS;
+        // f2 g2 h2: a full shield.
+        assert_eq!(
+            king_safety_of("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1", Color::White),
+            w.pawn_shield[0] * 3
+        );
+        // f2 g3 and no h-pawn, with Black's h-pawn still at home.
+        assert_eq!(
+            king_safety_of("6k1/5ppp/8/8/8/6P1/5P2/6K1 w - - 0 1", Color::White),
+            w.pawn_shield[0] + w.pawn_shield[1] + w.king_semi_open_file
+        );
+        // No pawns at all on the king's files.
+        assert_eq!(
+            king_safety_of("6k1/8/8/8/8/8/8/6K1 w - - 0 1", Color::White),
+            w.king_open_file * 3
+        );
+    }
+
+    #[test]
+    fn test_pawn_storm() {
+        let w = &WEIGHTS;
+        // Black's g-pawn on g3, two ranks in front of the king.
+        assert_eq!(
+            king_safety_of("6k1/8/8/8/8/6p1/5P1P/6K1 w - - 0 1", Color::White),
+            w.pawn_shield[0] * 2 + w.king_semi_open_file + w.pawn_storm[1]
+        );
+    }
+
+    #[test]
+    fn test_attacks_grow_faster_than_linearly() {
+        // The cost to Black's king of white pieces on the fifth rank: a queen
+        // on h5 and a knight on g5 both hit f7 and h7.
+        let cost = |pieces: &str| {
+            let fen = format!("6k1/5ppp/8/{pieces}/8/8/5PPP/6K1 b - - 0 1");
+            king_safety_of("6k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", Color::Black).mg()
+                - king_safety_of(&fen, Color::Black).mg()
+        };
+        let queen = cost("7Q");
+        let knight = cost("6N1");
+        assert!(queen > 0 && knight > 0, "{queen} {knight}");
+        assert!(cost("6NQ") > queen + knight);
+    }
+
+    #[test]
+    fn test_every_attacker_counts() {
+        // The knight on g5 hits f7 and h7, the one on e5 f7 again.
+        let units = |pieces: &str| {
+            let fen = format!("6k1/5ppp/8/{pieces}/8/8/5PPP/6K1 b - - 0 1");
+            let state = State::from_fen(&fen).unwrap();
+            attack_units(&state, &WEIGHTS, &state.attack_maps(), Color::Black)
+        };
+        let knight = WEIGHTS.king_attack_weight[PieceType::Knight as usize];
+        assert_eq!(units("6N1"), 2 * knight);
+        assert_eq!(units("4N3"), knight);
+        assert_eq!(units("4N1N1"), 3 * knight);
+    }
+}
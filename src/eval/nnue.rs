@@ -0,0 +1,432 @@
+use crate::board::state::{Color, PieceType, Square, State};
+use crate::search::{MATE, MAX_PLY};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use strum::IntoEnumIterator;
+
+// An efficiently updatable neural network evaluation with a (768 -> N) x 2
+// -> 1 layout. Each of the 768 inputs is one kind of piece (color and type)
+// on one square. Both sides see the position from their own perspective: a
+// shared layer of N neurons turns each perspective into an accumulator, and
+// the output neuron reads the clipped accumulators, side to move first.
+// Accumulators are sums over the pieces on the board, so `State` keeps them
+// up to date as pieces come and go instead of recomputing them at each node.
+//
+// Weights are integers. Accumulators are scaled by `QA` and clipped to
+// `[0, QA]`, output weights are scaled by `QB`, and the output is in sigmoid
+// space, `SCALE` to a centipawn.
+//
+// The weights file, all little-endian:
+//
+//     magic            4 bytes, "PNUE"
+//     hidden size      u32, N
+//     feature weights  768 x N i16, by input
+//     feature biases   N i16
+//     output weights   2 x N i16, side to move first
+//     output bias      i32
+
+pub const INPUTS: usize = 768;
+pub const QA: i32 = 255;
+pub const QB: i32 = 64;
+pub const SCALE: i32 = 400;
+pub const MAX_HIDDEN: usize = 4096;
+
+const MAGIC: [u8; 4] = *b"PNUE";
+
+// The output is clamped below the scores the search reads as mates.
+const MAX_SCORE: i32 = MATE - MAX_PLY as i32 - 1;
+
+/// Why a weights file could not be loaded.
+#[derive(Debug)]
+pub enum NnueError {
+    Io(io::Error),
+    BadMagic,
+    BadHiddenSize(usize),
+    WrongLength { expected: usize, found: usize },
+}
+
+impl fmt::Display for NnueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use NnueError::*;
+        match self {
+            Io(error) => write!(f, "{error}"),
+            BadMagic => write!(f, "not a network file"),
+            BadHiddenSize(size) => {
+                write!(
+                    f,
+                    "hidden layer size {size} is not between 1 and {MAX_HIDDEN}"
+                )
+            }
+            WrongLength { expected, found } => {
+                write!(f, "expected {expected} bytes, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NnueError {}
+
+impl From<io::Error> for NnueError {
+    fn from(error: io::Error) -> Self {
+        NnueError::Io(error)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Network({INPUTS} -> {}) x 2 -> 1", self.hidden)
+    }
+}
+
+impl Network {
+    /// A network with the given quantized weights, laid out as in the weights
+    /// file. Panics when the lengths do not agree with one another.
+    pub fn new(
+        feature_weights: Vec<i16>,
+        feature_biases: Vec<i16>,
+        output_weights: Vec<i16>,
+        output_bias: i32,
+    ) -> Network {
+        let hidden = feature_biases.len();
+        assert!((1..=MAX_HIDDEN).contains(&hidden));
+        assert_eq!(feature_weights.len(), INPUTS * hidden);
+        assert_eq!(output_weights.len(), 2 * hidden);
+        Network {
+            hidden,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        }
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Network, NnueError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NnueError> {
+        if bytes.len() < 8 || bytes[..4] != MAGIC {
+            return Err(NnueError::BadMagic);
+        }
+        let hidden = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        if !(1..=MAX_HIDDEN).contains(&hidden) {
+            return Err(NnueError::BadHiddenSize(hidden));
+        }
+        let expected = 8 + 2 * (INPUTS * hidden + hidden + 2 * hidden) + 4;
+        if bytes.len() != expected {
+            return Err(NnueError::WrongLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let mut rest = &bytes[8..];
+        let mut take = |count: usize| {
+            let (values, tail) = rest.split_at(2 * count);
+            rest = tail;
+            values
+                .chunks_exact(2)
+                .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                .collect::<Vec<i16>>()
+        };
+        let feature_weights = take(INPUTS * hidden);
+        let feature_biases = take(hidden);
+        let output_weights = take(2 * hidden);
+        let output_bias = i32::from_le_bytes(rest.try_into().unwrap());
+        Ok(Network::new(
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((self.hidden as u32).to_le_bytes());
+        for values in [
+            &self.feature_weights,
+            &self.feature_biases,
+            &self.output_weights,
+        ] {
+            bytes.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+        }
+        bytes.extend(self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    fn weights_of(&self, input: usize) -> &[i16] {
+        &self.feature_weights[input * self.hidden..(input + 1) * self.hidden]
+    }
+}
+
+/// The input for a `color` `piece` on `square` as seen from `perspective`:
+/// its own pieces come first, and Black sees the board flipped.
+pub fn feature_index(perspective: Color, piece: PieceType, color: Color, square: Square) -> usize {
+    let side = usize::from(color != perspective);
+    let square = match perspective {
+        Color::White => square as usize,
+        // Ranks are the low three bits of a square.
+        Color::Black => square as usize ^ 7,
+    };
+    side * 384 + piece as usize * 64 + square
+}
+
+/// The hidden layer sums of a position for both perspectives.
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    // Indexed by the perspective's `Color`.
+    values: [Vec<i16>; 2],
+}
+
+impl fmt::Debug for Accumulator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Accumulator")
+            .field("network", &self.network)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Accumulator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.network, &other.network) && self.values == other.values
+    }
+}
+
+impl Eq for Accumulator {}
+
+impl Accumulator {
+    /// Computes the accumulators of `state` from scratch.
+    pub fn new(network: Arc<Network>, state: &State) -> Accumulator {
+        let biases = network.feature_biases.clone();
+        let mut accumulator = Accumulator {
+            network,
+            values: [biases.clone(), biases],
+        };
+        for color in Color::iter() {
+            for piece in PieceType::iter() {
+                for square in state.get_bitboard_for(piece, color).squares() {
+                    accumulator.add(piece, color, square);
+                }
+            }
+        }
+        accumulator
+    }
+
+    pub fn add(&mut self, piece: PieceType, color: Color, square: Square) {
+        for perspective in Color::iter() {
+            let weights = self
+                .network
+                .weights_of(feature_index(perspective, piece, color, square));
+            for (value, &weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value = value.wrapping_add(weight);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, piece: PieceType, color: Color, square: Square) {
+        for perspective in Color::iter() {
+            let weights = self
+                .network
+                .weights_of(feature_index(perspective, piece, color, square));
+            for (value, &weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value = value.wrapping_sub(weight);
+            }
+        }
+    }
+
+    /// The network's evaluation in centipawns, from the point of view of
+    /// `side_to_move`. Large networks can add up to more than an `i32`
+    /// holds, so the sum is an `i64`, and the result is kept short of mate
+    /// scores.
+    pub fn evaluate(&self, side_to_move: Color) -> i32 {
+        let hidden = self.network.hidden;
+        let (own, their) = self.network.output_weights.split_at(hidden);
+        let sum = clipped_dot(&self.values[side_to_move as usize], own)
+            + clipped_dot(&self.values[side_to_move.opposite() as usize], their);
+        let score =
+            (sum + i64::from(self.network.output_bias)) * i64::from(SCALE) / i64::from(QA * QB);
+        score.clamp(-i64::from(MAX_SCORE), i64::from(MAX_SCORE)) as i32
+    }
+}
+
+// Plain loops over slices of equal length, which the compiler vectorizes.
+// Each product fits an `i32`; their sum may not.
+fn clipped_dot(values: &[i16], weights: &[i16]) -> i64 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&value, &weight)| {
+            i64::from(i32::from(value.clamp(0, QA as i16)) * i32::from(weight))
+        })
+        .sum()
+}
+
+static NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+
+/// The network positions are set up with, if one is loaded.
+pub fn network() -> Option<Arc<Network>> {
+    NETWORK.read().unwrap().clone()
+}
+
+/// Makes `network` the one positions set up from now on evaluate with, or
+/// goes back to the hand-crafted evaluation with `None`.
+pub fn set_network(network: Option<Arc<Network>>) {
+    *NETWORK.write().unwrap() = network;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::moves::Move;
+    use crate::eval::{evaluate, evaluate_with, WEIGHTS};
+
+    /// A network with small pseudo-random weights.
+    fn random_network(hidden: usize) -> Arc<Network> {
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = |range: i16| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % (2 * range as u64 + 1)) as i16 - range
+        };
+        let feature_weights = (0..INPUTS * hidden).map(|_| next(40)).collect();
+        let feature_biases = (0..hidden).map(|_| next(60)).collect();
+        let output_weights = (0..2 * hidden).map(|_| next(100)).collect();
+        Arc::new(Network::new(
+            feature_weights,
+            feature_biases,
+            output_weights,
+            1000,
+        ))
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let network = random_network(8);
+        let bytes = network.to_bytes();
+        assert_eq!(bytes.len(), 8 + 2 * (768 * 8 + 8 + 16) + 4);
+        assert_eq!(Network::from_bytes(&bytes).unwrap(), *network);
+
+        assert!(matches!(
+            Network::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(NnueError::WrongLength { .. })
+        ));
+        assert!(matches!(
+            Network::from_bytes(b"nope"),
+            Err(NnueError::BadMagic)
+        ));
+        let mut huge = bytes.clone();
+        huge[4..8].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            Network::from_bytes(&huge),
+            Err(NnueError::BadHiddenSize(0))
+        ));
+    }
+
+    #[test]
+    fn test_feature_index() {
+        // A white pawn on e2 for White is a black pawn on e7 for Black.
+        assert_eq!(
+            feature_index(Color::White, PieceType::Pawn, Color::White, Square::E2),
+            feature_index(Color::Black, PieceType::Pawn, Color::Black, Square::E7)
+        );
+        assert_eq!(
+            feature_index(Color::White, PieceType::King, Color::Black, Square::H8),
+            767
+        );
+    }
+
+    #[test]
+    fn test_accumulator_follows_moves() {
+        let network = random_network(16);
+        let mut state =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        state.set_network(Some(Arc::clone(&network)));
+        let initial = state.clone();
+
+        // Castling, captures and a promotion, then en passant.
+        for line in [
+            &["e1g1", "h3g2", "f3f6", "g2f1q", "g1f1"][..],
+            &["a2a4", "b4a3"],
+        ] {
+            for text in line {
+                state.make_move(Move::from_uci(text, &state).unwrap());
+                let fresh = Accumulator::new(Arc::clone(&network), &state);
+                assert_eq!(state.accumulator(), Some(&fresh), "{text}");
+            }
+            for _ in line {
+                state.unmake_move();
+            }
+            assert_eq!(state, initial);
+        }
+    }
+
+    #[test]
+    fn test_evaluation_is_symmetric() {
+        let network = random_network(16);
+        let eval = |fen: &str| {
+            let mut state = State::from_fen(fen).unwrap();
+            state.set_network(Some(Arc::clone(&network)));
+            evaluate(&state)
+        };
+        assert_eq!(
+            eval("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"),
+            eval("4k3/4p3/8/8/8/8/8/4K3 b - - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_saturated_network_stays_short_of_mate() {
+        // Every accumulator clipped to `QA` against the largest output
+        // weights the trainer writes.
+        let saturated = |weight: i16| {
+            let hidden = 256;
+            Arc::new(Network::new(
+                vec![0; INPUTS * hidden],
+                vec![QA as i16; hidden],
+                vec![weight; 2 * hidden],
+                0,
+            ))
+        };
+        let state = State::new();
+        let winning = Accumulator::new(saturated(127), &state);
+        assert_eq!(winning.evaluate(Color::White), MAX_SCORE);
+        let losing = Accumulator::new(saturated(-127), &state);
+        assert_eq!(losing.evaluate(Color::Black), -MAX_SCORE);
+        assert!(MAX_SCORE < MATE - MAX_PLY as i32);
+    }
+
+    #[test]
+    fn test_network_replaces_hand_crafted_eval() {
+        let mut state = State::new();
+        assert_eq!(state.accumulator(), None);
+        assert_eq!(evaluate(&state), evaluate_with(&state, &WEIGHTS));
+
+        state.set_network(Some(random_network(16)));
+        let accumulator = state.accumulator().unwrap();
+        assert_eq!(evaluate(&state), accumulator.evaluate(Color::White));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::board::bitboard::{pawn_attacks, Bitboard, Bits};
+use crate::board::state::{Color, PieceType, Square, State};
+use crate::eval::weights::{Weights, S};
+use strum::IntoEnumIterator;
+
+// Masks are built on the file-major layout: each file is one byte, bit n of
+// which is rank n + 1.
+const FILE_A: Bits = 0xFF;
+pub(super) const RANK_1: Bits = 0x0101_0101_0101_0101;
+
+// 16384 entries of 24 bytes.
+const PAWN_TABLE_ENTRIES: usize = 1 << 14;
+
+/// The part of the evaluation that depends on the pawns alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PawnEval {
+    /// White's pawn structure terms minus Black's.
+    pub score: S,
+    /// The passed pawns of both sides.
+    pub passed: Bitboard,
+}
+
+/// Evaluates the pawn structure of `state`: doubled, isolated, backward,
+/// connected and passed pawns.
+pub fn evaluate(state: &State, weights: &Weights) -> PawnEval {
+    let mut eval = PawnEval::default();
+    for color in Color::iter() {
+        let (score, passed) = evaluate_side(state, weights, color);
+        match color {
+            Color::White => eval.score += score,
+            Color::Black => eval.score -= score,
+        }
+        eval.passed |= passed;
+    }
+    eval
+}
+
+/// The bonus for the `passed` pawns with a free path to promotion. Unlike the
+/// rest of the pawn evaluation this depends on the other pieces, so it is not
+/// cached.
+pub fn free_passed_pawns(state: &State, weights: &Weights, passed: Bitboard) -> S {
+    let occupied = state.occupied().get_bits();
+    let mut score = S::ZERO;
+    for color in Color::iter() {
+        let pawns = passed & state.get_bitboard_for(PieceType::Pawn, color);
+        for square in pawns.squares() {
+            let path = file_mask(square.file()) & ranks_ahead(color, square.rank());
+            if path & occupied == 0 {
+                let bonus = weights.free_passed_pawn[relative_rank(color, square)];
+                match color {
+                    Color::White => score += bonus,
+                    Color::Black => score -= bonus,
+                }
+            }
+        }
+    }
+    score
+}
+
+fn evaluate_side(state: &State, weights: &Weights, color: Color) -> (S, Bitboard) {
+    let own = state.get_bitboard_for(PieceType::Pawn, color);
+    let enemy = state
+        .get_bitboard_for(PieceType::Pawn, color.opposite())
+        .get_bits();
+    let defended = pawn_attacks(color, own).get_bits();
+    let enemy_attacks = pawn_attacks(color.opposite(), Bitboard::from_bits(enemy)).get_bits();
+    let own_bits = own.get_bits();
+
+    let mut score = S::ZERO;
+    let mut passed = Bitboard::EMPTY;
+    for square in own.squares() {
+        let bit = 1 << square as u8;
+        let rank = relative_rank(color, square);
+        let ahead = ranks_ahead(color, square.rank());
+        let front = file_mask(square.file()) & ahead;
+        let neighbors = adjacent_files(square.file());
+        let supported = defended & bit != 0;
+        let phalanx = own_bits & neighbors & (RANK_1 << square.rank()) != 0;
+
+        if own_bits & front != 0 {
+            score += weights.doubled_pawn;
+        }
+        if supported || phalanx {
+            score += weights.connected_pawn[rank];
+        }
+        if own_bits & neighbors == 0 {
+            score += weights.isolated_pawn;
+        } else if !supported && own_bits & neighbors & !ahead == 0 {
+            let stop = match color {
+                Color::White => bit << 1,
+                Color::Black => bit >> 1,
+            };
+            if enemy_attacks & stop != 0 {
+                score += weights.backward_pawn;
+            }
+        }
+        // Of doubled pawns only the front one can be passed.
+        if enemy & (front | neighbors & ahead) == 0 && own_bits & front == 0 {
+            score += weights.passed_pawn[rank];
+            passed.set_square(square);
+        }
+    }
+    (score, passed)
+}
+
+/// The rank of `square` counted from `color`'s side, 0 to 7.
+pub(super) fn relative_rank(color: Color, square: Square) -> usize {
+    match color {
+        Color::White => square.rank() as usize,
+        Color::Black => 7 - square.rank() as usize,
+    }
+}
+
+pub(super) fn file_mask(file: u8) -> Bits {
+    FILE_A << (8 * file)
+}
+
+pub(super) fn adjacent_files(file: u8) -> Bits {
+    let west = if file > 0 { file_mask(file - 1) } else { 0 };
+    let east = if file < 7 { file_mask(file + 1) } else { 0 };
+    west | east
+}
+
+/// The ranks strictly in front of `rank` as seen by `color`, on every file.
+pub(super) fn ranks_ahead(color: Color, rank: u8) -> Bits {
+    let ranks = match color {
+        Color::White => (0xFF << (rank + 1)) & 0xFF,
+        Color::Black => (1 << rank) - 1,
+    };
+    ranks * RANK_1
+}
+
+#[derive(Clone, Copy, Default)]
+struct PawnEntry {
+    key: u64,
+    eval: PawnEval,
+}
+
+/// A cache of pawn evaluations under [`State::pawn_hash`]. Pawn structures
+/// repeat across most of the nodes of a search, so this saves nearly all of
+/// the pawn evaluation work. Only the built-in weights are cached.
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        // An empty entry has key zero, the key of a board without pawns, and
+        // its evaluation is the right one for it.
+        PawnTable {
+            entries: vec![PawnEntry::default(); PAWN_TABLE_ENTRIES],
+        }
+    }
+
+    /// The pawn evaluation of `state` with `weights`, which must be the
+    /// weights used for every other probe.
+    pub fn probe(&mut self, state: &State, weights: &Weights) -> PawnEval {
+        let key = state.pawn_hash();
+        let entry = &mut self.entries[key as usize & (PAWN_TABLE_ENTRIES - 1)];
+        if entry.key != key {
+            *entry = PawnEntry {
+                key,
+                eval: evaluate(state, weights),
+            };
+        }
+        entry.eval
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::WEIGHTS;
+
+    fn pawns(fen: &str) -> PawnEval {
+        evaluate(&State::from_fen(fen).unwrap(), &WEIGHTS)
+    }
+
+    fn passed(fen: &str) -> Vec<String> {
+        pawns(fen)
+            .passed
+            .squares()
+            .map(|square| format!("{square:?}").to_lowercase())
+            .collect()
+    }
+
+    #[test]
+    fn test_start_position() {
+        assert_eq!(pawns(crate::board::state::START_FEN), PawnEval::default());
+    }
+
+    #[test]
+    fn test_structure_terms() {
+        let w = &WEIGHTS;
+        // Doubled and isolated on the c-file against a healthy pair.
+        assert_eq!(
+            pawns("4k3/1pp5/8/8/8/2P5/2P5/4K3 w - - 0 1").score,
+            w.doubled_pawn + w.isolated_pawn * 2 - w.connected_pawn[1] * 2
+        );
+        // The d2 pawn cannot be supported and d3 is covered by the e4 pawn,
+        // which has no neighbors; c4 is passed.
+        assert_eq!(
+            pawns("4k3/8/8/8/2P1p3/8/3P4/4K3 w - - 0 1").score,
+            w.backward_pawn + w.passed_pawn[3] - w.isolated_pawn
+        );
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        // The e-pawns block each other, the d7 pawn is held by e4 and the
+        // rear g-pawn is behind its twin.
+        assert_eq!(
+            passed("4k3/3p4/8/4p1P1/P3P1P1/7p/8/4K3 w - - 0 1"),
+            ["a4", "g5", "h3"]
+        );
+        let w = &WEIGHTS;
+        let state = State::from_fen("4k3/P7/8/8/8/8/7p/4K3 w - - 0 1").unwrap();
+        let eval = evaluate(&state, w);
+        assert_eq!(eval.score, S::ZERO);
+        assert_eq!(free_passed_pawns(&state, w, eval.passed), S::ZERO);
+        // A knight in front of the a-pawn blocks it.
+        let state = State::from_fen("n3k3/P7/8/8/8/8/7p/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            free_passed_pawns(&state, w, eval.passed),
+            -w.free_passed_pawn[6]
+        );
+    }
+
+    #[test]
+    fn test_table_matches_evaluation() {
+        let mut table = PawnTable::new();
+        for fen in [
+            "4k3/3p4/8/4p1P1/P3P1P1/7p/8/4K3 w - - 0 1",
+            "4k3/8/8/8/2P1p3/8/3P4/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            let state = State::from_fen(fen).unwrap();
+            assert_eq!(table.probe(&state, &WEIGHTS), evaluate(&state, &WEIGHTS));
+            assert_eq!(table.probe(&state, &WEIGHTS), evaluate(&state, &WEIGHTS));
+        }
+    }
+}
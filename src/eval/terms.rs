@@ -0,0 +1,98 @@
+use crate::board::state::{Color, PieceType, Square, State};
+use crate::eval::weights::{psqt_index, Weights, S};
+use crate::eval::{PHASE_WEIGHTS, WEIGHTS};
+use strum::IntoEnumIterator;
+
+/// The evaluation terms that are sums over the pieces, kept up to date by
+/// `State` as pieces come and go so they need not be recomputed at every
+/// node. Material and placement are White's minus Black's, with the built-in
+/// weights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvalTerms {
+    pub material: S,
+    pub psqt: S,
+    pub phase: i32,
+}
+
+impl EvalTerms {
+    /// Sums the terms over every piece of `state` using `weights`.
+    pub fn compute_with(state: &State, weights: &Weights) -> EvalTerms {
+        let mut terms = EvalTerms::default();
+        for color in Color::iter() {
+            for piece in PieceType::iter() {
+                for square in state.get_bitboard_for(piece, color).squares() {
+                    terms.add_with(weights, piece, color, square);
+                }
+            }
+        }
+        terms
+    }
+
+    pub fn compute(state: &State) -> EvalTerms {
+        Self::compute_with(state, &WEIGHTS)
+    }
+
+    pub fn add(&mut self, piece: PieceType, color: Color, square: Square) {
+        self.add_with(&WEIGHTS, piece, color, square);
+    }
+
+    pub fn remove(&mut self, piece: PieceType, color: Color, square: Square) {
+        self.update(&WEIGHTS, piece, color, square, -1);
+    }
+
+    fn add_with(&mut self, weights: &Weights, piece: PieceType, color: Color, square: Square) {
+        self.update(weights, piece, color, square, 1);
+    }
+
+    // `sign` is 1 when the piece appears and -1 when it goes.
+    fn update(
+        &mut self,
+        weights: &Weights,
+        piece: PieceType,
+        color: Color,
+        square: Square,
+        sign: i32,
+    ) {
+        let side = match color {
+            Color::White => sign,
+            Color::Black => -sign,
+        };
+        self.material += weights.material[piece as usize] * side;
+        self.psqt += weights.psqt[piece as usize][psqt_index(color, square)] * side;
+        self.phase += PHASE_WEIGHTS[piece as usize] * sign;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::moves::Move;
+
+    #[test]
+    fn test_terms_follow_moves() {
+        let mut state =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let initial = state.eval_terms();
+        assert_eq!(initial, EvalTerms::compute(&state));
+        assert_eq!(initial.phase, 24);
+
+        // Castling, captures and a promotion.
+        for text in ["e1g1", "h3g2", "f3f6", "g2f1q"] {
+            state.make_move(Move::from_uci(text, &state).unwrap());
+            assert_eq!(state.eval_terms(), EvalTerms::compute(&state), "{text}");
+        }
+        for _ in 0..4 {
+            state.unmake_move();
+        }
+        assert_eq!(state.eval_terms(), initial);
+    }
+
+    #[test]
+    fn test_start_position_terms() {
+        let terms = State::new().eval_terms();
+        assert_eq!(terms.material, S::ZERO);
+        assert_eq!(terms.psqt, S::ZERO);
+        assert_eq!(terms.phase, 24);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::board::state::{Color, PieceType, Square};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+/// A weight with a midgame and an endgame value, blended by the game phase.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct S(pub i32, pub i32);
+
+impl S {
+    pub const ZERO: S = S(0, 0);
+
+    pub fn mg(self) -> i32 {
+        self.0
+    }
+
+    pub fn eg(self) -> i32 {
+        self.1
+    }
+}
+
+impl Add for S {
+    type Output = S;
+
+    fn add(self, other: S) -> S {
+        S(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for S {
+    type Output = S;
+
+    fn sub(self, other: S) -> S {
+        S(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Neg for S {
+    type Output = S;
+
+    fn neg(self) -> S {
+        S(-self.0, -self.1)
+    }
+}
+
+impl Mul<i32> for S {
+    type Output = S;
+
+    fn mul(self, factor: i32) -> S {
+        S(self.0 * factor, self.1 * factor)
+    }
+}
+
+impl Div<i32> for S {
+    type Output = S;
+
+    fn div(self, divisor: i32) -> S {
+        S(self.0 / divisor, self.1 / divisor)
+    }
+}
+
+impl AddAssign for S {
+    fn add_assign(&mut self, other: S) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for S {
+    fn sub_assign(&mut self, other: S) {
+        *self = *self - other;
+    }
+}
+
+/// Every weight of the hand-crafted evaluation. The values in use are
+/// [`crate::eval::WEIGHTS`]; other instances are for tuning.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Weights {
+    /// Indexed by `PieceType`.
+    pub material: [S; 6],
+    /// Bonuses by `PieceType` and square, laid out as the board is drawn from
+    /// White's side: a8 first, h1 last. Black uses the mirrored square.
+    pub psqt: [[S; 64]; 6],
+    /// Per pawn with another pawn of its color in front of it on its file.
+    pub doubled_pawn: S,
+    /// Per pawn with no pawn of its color on the neighboring files.
+    pub isolated_pawn: S,
+    /// Per pawn that has fallen behind the pawns of its color on the
+    /// neighboring files and cannot advance safely.
+    pub backward_pawn: S,
+    /// Per pawn defended by or standing beside a pawn of its color, by rank
+    /// counted from its own side.
+    pub connected_pawn: [S; 8],
+    /// Per passed pawn, by rank counted from its own side.
+    pub passed_pawn: [S; 8],
+    /// Added to `passed_pawn` when nothing stands between the pawn and its
+    /// promotion square.
+    pub free_passed_pawn: [S; 8],
+    /// Attack units per square of the zone around the enemy king that a
+    /// piece attacks, indexed by `PieceType`. Each attacker of a square
+    /// counts.
+    pub king_attack_weight: [i32; 6],
+    /// The king danger penalty per squared attack unit, in 1/64ths.
+    pub king_danger: S,
+    /// Per file next to the king with a pawn of its color one or two ranks
+    /// in front of it.
+    pub pawn_shield: [S; 2],
+    /// Per file next to the king with an enemy pawn one to four ranks in
+    /// front of it, by that distance.
+    pub pawn_storm: [S; 4],
+    /// Per file next to the king without pawns of either color.
+    pub king_open_file: S,
+    /// Per file next to the king with only enemy pawns.
+    pub king_semi_open_file: S,
+    /// Per knight, by the number of squares it attacks that are neither held
+    /// by a piece of its color nor attacked by an enemy pawn.
+    pub knight_mobility: [S; 9],
+    /// Per bishop, by its safe squares as for knights.
+    pub bishop_mobility: [S; 14],
+    /// Per rook, by its safe squares as for knights.
+    pub rook_mobility: [S; 15],
+    /// Per queen, by its safe squares as for knights.
+    pub queen_mobility: [S; 28],
+    /// For a side with two or more bishops.
+    pub bishop_pair: S,
+    /// Per rook on a file without pawns.
+    pub rook_open_file: S,
+    /// Per rook on a file with enemy pawns only.
+    pub rook_semi_open_file: S,
+    /// Per rook on the seventh rank with the enemy king on the eighth or
+    /// enemy pawns on the seventh.
+    pub rook_on_seventh: S,
+    /// Per knight on the fourth to sixth rank, defended by a pawn and out of
+    /// reach of the enemy pawns.
+    pub knight_outpost: S,
+    /// Per bishop shut in on a7 or h7 by an enemy pawn on b6 or g6.
+    pub trapped_bishop: S,
+    /// Per rook hemmed in on the first rank by its uncastled king.
+    pub trapped_rook: S,
+}
+
+/// The values of one field of [`Weights`], for code that goes through all of
+/// them.
+pub enum Field<'a> {
+    Scalar(&'a mut S),
+    Row(&'a mut [S]),
+    /// A piece-square table per piece type.
+    Tables(&'a mut [[S; 64]]),
+    Ints(&'a mut [i32]),
+}
+
+impl Weights {
+    /// Calls `visit` with the name and values of every field, in the order
+    /// they are declared.
+    pub fn for_each_field(&mut self, mut visit: impl FnMut(&'static str, Field<'_>)) {
+        visit("material", Field::Row(&mut self.material));
+        visit("psqt", Field::Tables(&mut self.psqt));
+        visit("doubled_pawn", Field::Scalar(&mut self.doubled_pawn));
+        visit("isolated_pawn", Field::Scalar(&mut self.isolated_pawn));
+        visit("backward_pawn", Field::Scalar(&mut self.backward_pawn));
+        visit("connected_pawn", Field::Row(&mut self.connected_pawn));
+        visit("passed_pawn", Field::Row(&mut self.passed_pawn));
+        visit("free_passed_pawn", Field::Row(&mut self.free_passed_pawn));
+        visit(
+            "king_attack_weight",
+            Field::Ints(&mut self.king_attack_weight),
+        );
+        visit("king_danger", Field::Scalar(&mut self.king_danger));
+        visit("pawn_shield", Field::Row(&mut self.pawn_shield));
+        visit("pawn_storm", Field::Row(&mut self.pawn_storm));
+        visit("king_open_file", Field::Scalar(&mut self.king_open_file));
+        visit(
+            "king_semi_open_file",
+            Field::Scalar(&mut self.king_semi_open_file),
+        );
+        visit("knight_mobility", Field::Row(&mut self.knight_mobility));
+        visit("bishop_mobility", Field::Row(&mut self.bishop_mobility));
+        visit("rook_mobility", Field::Row(&mut self.rook_mobility));
+        visit("queen_mobility", Field::Row(&mut self.queen_mobility));
+        visit("bishop_pair", Field::Scalar(&mut self.bishop_pair));
+        visit("rook_open_file", Field::Scalar(&mut self.rook_open_file));
+        visit(
+            "rook_semi_open_file",
+            Field::Scalar(&mut self.rook_semi_open_file),
+        );
+        visit("rook_on_seventh", Field::Scalar(&mut self.rook_on_seventh));
+        visit("knight_outpost", Field::Scalar(&mut self.knight_outpost));
+        visit("trapped_bishop", Field::Scalar(&mut self.trapped_bishop));
+        visit("trapped_rook", Field::Scalar(&mut self.trapped_rook));
+    }
+
+    /// Every weight as one number, midgame values before endgame values, in
+    /// the order of [`Weights::for_each_field`].
+    pub fn to_params(&self) -> Vec<i32> {
+        let mut params = Vec::new();
+        self.clone().for_each_field(|_, field| match field {
+            Field::Scalar(value) => params.extend([value.0, value.1]),
+            Field::Row(values) => params.extend(values.iter().flat_map(|value| [value.0, value.1])),
+            Field::Tables(tables) => {
+                params.extend(tables.iter().flatten().flat_map(|value| [value.0, value.1]))
+            }
+            Field::Ints(values) => params.extend_from_slice(values),
+        });
+        params
+    }
+
+    /// Sets every weight from `params`, laid out as by [`Weights::to_params`].
+    pub fn set_params(&mut self, params: &[i32]) {
+        let mut params = params.iter().copied();
+        let mut next = || params.next().expect("a value for every weight");
+        self.for_each_field(|_, field| match field {
+            Field::Scalar(value) => *value = S(next(), next()),
+            Field::Row(values) => values
+                .iter_mut()
+                .for_each(|value| *value = S(next(), next())),
+            Field::Tables(tables) => tables
+                .iter_mut()
+                .flatten()
+                .for_each(|value| *value = S(next(), next())),
+            Field::Ints(values) => values.iter_mut().for_each(|value| *value = next()),
+        });
+    }
+
+    /// The material and placement value of a piece on `square`.
+    pub fn piece_square(&self, piece: PieceType, color: Color, square: Square) -> S {
+        self.material[piece as usize] + self.psqt[piece as usize][psqt_index(color, square)]
+    }
+}
+
+/// Where `square` is found in a piece-square table for `color`.
+pub fn psqt_index(color: Color, square: Square) -> usize {
+    let rank = match color {
+        Color::White => 7 - square.rank(),
+        Color::Black => square.rank(),
+    };
+    rank as usize * 8 + square.file() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::WEIGHTS;
+
+    #[test]
+    fn test_params_cover_every_weight() {
+        let params = WEIGHTS.to_params();
+        // Weights holds nothing but i32 values.
+        assert_eq!(params.len() * 4, std::mem::size_of::<Weights>());
+
+        let mut weights = WEIGHTS.clone();
+        let shifted: Vec<i32> = params.iter().map(|param| param + 1).collect();
+        weights.set_params(&shifted);
+        assert_eq!(weights.material[0], WEIGHTS.material[0] + S(1, 1));
+        assert_eq!(weights.to_params(), shifted);
+        weights.set_params(&params);
+        assert_eq!(weights, WEIGHTS);
+    }
+}
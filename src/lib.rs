@@ -0,0 +1,6 @@
+pub mod board;
+pub mod eval;
+pub mod protocol;
+pub mod search;
+pub mod uci;
+pub mod xboard;
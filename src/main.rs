@@ -1,9 +1,33 @@
-use crate::board::state::Color::White;
-use crate::board::state::PieceType::Knight;
-
-mod board;
+use pato::board;
+use pato::board::state::State;
+use pato::protocol;
+use std::env;
+use std::io;
+use std::process;
 
 fn main() {
-    let state = board::state::State::new();
-    board::display::print_board(&state);
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("perft") => run_perft(&args[1..]),
+        _ => protocol::run(io::stdin().lock()),
+    }
+}
+
+/// `pato perft <depth> [fen]` prints the perft divide counts for the position.
+fn run_perft(args: &[String]) {
+    let depth = args.first().and_then(|depth| depth.parse().ok());
+    let Some(depth) = depth else {
+        eprintln!("usage: pato perft <depth> [fen]");
+        process::exit(2);
+    };
+    let mut state = if args.len() > 1 {
+        State::from_fen(&args[1..].join(" ")).unwrap_or_else(|error| {
+            eprintln!("invalid FEN: {error}");
+            process::exit(2);
+        })
+    } else {
+        State::new()
+    };
+    board::perft::print_divide(&mut state, depth, &mut io::stdout().lock())
+        .expect("writing to stdout");
 }
@@ -0,0 +1,53 @@
+use crate::uci::Uci;
+use crate::xboard::XBoard;
+use std::io::{self, BufRead};
+
+/// Talks to a GUI over standard input and output until it quits. The first
+/// command decides the protocol: `xboard` selects CECP, anything else UCI.
+pub fn run(input: impl BufRead) {
+    let mut lines = input.lines().map_while(Result::ok);
+    let Some(first) = lines.find(|line| !line.trim().is_empty()) else {
+        return;
+    };
+
+    let mut handle_command: Box<dyn FnMut(&str) -> bool> = if first.trim() == "xboard" {
+        let mut xboard = XBoard::new(io::stdout());
+        Box::new(move |line| xboard.handle_command(line))
+    } else {
+        let mut uci = Uci::new(io::stdout());
+        Box::new(move |line| uci.handle_command(line))
+    };
+
+    if !handle_command(&first) {
+        return;
+    }
+    for line in lines {
+        if !handle_command(&line) {
+            break;
+        }
+    }
+}
+
+/// An output buffer tests can read while a protocol handler writes to it.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl io::Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl SharedBuffer {
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let text = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(String::from).collect()
+    }
+}
@@ -0,0 +1,518 @@
+use crate::board::moves::Move;
+use crate::board::see::SEE_VALUES;
+use crate::board::state::{Color, PieceType, State};
+use crate::eval::Evaluator;
+use ordering::order_moves;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tt::{Bound, Entry};
+
+mod limits;
+mod ordering;
+mod thread;
+mod tt;
+
+pub use limits::SearchLimits;
+pub use thread::SearchThread;
+pub use tt::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB};
+
+/// The deepest the search goes below the root.
+pub const MAX_PLY: usize = 128;
+
+/// The score of delivering mate right now. Mates further away score lower by
+/// one point per ply, so the search prefers the shortest one.
+pub const MATE: i32 = 30_000;
+const INFINITY: i32 = MATE + 1;
+// Scores beyond this are mates within `MAX_PLY` plies.
+const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// A capture that cannot bring the score back up to alpha even with this much
+// positional gain on top of the captured piece is not searched.
+const DELTA_MARGIN: i32 = 200;
+
+// How many nodes are searched between looks at the clock.
+const CHECK_INTERVAL: u64 = 2048;
+
+/// A search result from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in the given number of moves; negative when the side to move is
+    /// getting mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// Converts an internal search score, where mates are `MATE` minus the
+    /// distance in plies.
+    pub fn from_internal(score: i32) -> Score {
+        if score >= MATE_BOUND {
+            Score::Mate((MATE - score + 1) / 2)
+        } else if score <= -MATE_BOUND {
+            Score::Mate(-(MATE + score) / 2)
+        } else {
+            Score::Centipawns(score)
+        }
+    }
+}
+
+/// Progress of a running search, reported after every completed iteration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub score: Score,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub pv: Vec<Move>,
+    /// How full the transposition table is, in permille.
+    pub hashfull: u32,
+}
+
+impl SearchInfo {
+    pub fn nodes_per_second(&self) -> u64 {
+        let micros = self.elapsed.as_micros().max(1);
+        (self.nodes as u128 * 1_000_000 / micros) as u64
+    }
+}
+
+/// Picks the move to play in `state` within `limits`, or `None` when the side
+/// to move has no legal move. Returns early once `stop` is set.
+///
+/// Runs an alpha-beta search to increasing depths, reporting each completed
+/// iteration, and plays the best move of the last one. An iteration that is
+/// interrupted is thrown away. Results are kept in `table` for later searches.
+pub fn search(
+    state: &mut State,
+    limits: &SearchLimits,
+    table: &TranspositionTable,
+    stop: &AtomicBool,
+    report: &mut dyn FnMut(&SearchInfo),
+) -> Option<Move> {
+    let root_moves = state.legal_moves();
+    let mut best_move = *root_moves.iter().next()?;
+
+    table.new_search();
+    let mut searcher = Searcher::new(limits, table, stop, state.side_to_move());
+    let max_depth = limits
+        .max_depth()
+        .unwrap_or(MAX_PLY as u32)
+        .min(MAX_PLY as u32);
+    for depth in 1..=max_depth {
+        let score = searcher.negamax(state, depth, 0, -INFINITY, INFINITY);
+        if searcher.stopped {
+            break;
+        }
+
+        let pv = searcher.principal_variation();
+        best_move = pv[0];
+        let info = SearchInfo {
+            depth,
+            score: Score::from_internal(score),
+            nodes: searcher.nodes,
+            elapsed: searcher.start.elapsed(),
+            pv,
+            hashfull: table.hashfull(),
+        };
+        report(&info);
+
+        if let (Some(moves), Score::Mate(found)) = (limits.mate, info.score) {
+            if found > 0 && found as u32 <= moves {
+                break;
+            }
+        }
+        // Another iteration takes several times as long as this one; give up
+        // rather than start one that cannot finish in time.
+        if searcher
+            .budget
+            .is_some_and(|budget| info.elapsed > budget / 2)
+        {
+            break;
+        }
+    }
+    Some(best_move)
+}
+
+struct Searcher<'a> {
+    limits: &'a SearchLimits,
+    table: &'a TranspositionTable,
+    stop: &'a AtomicBool,
+    start: Instant,
+    budget: Option<Duration>,
+    nodes: u64,
+    stopped: bool,
+    // Triangular table: the line found below each ply, starting at that ply.
+    pv_table: Vec<[Move; MAX_PLY]>,
+    pv_length: [usize; MAX_PLY + 1],
+    evaluator: Evaluator,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(
+        limits: &'a SearchLimits,
+        table: &'a TranspositionTable,
+        stop: &'a AtomicBool,
+        color: Color,
+    ) -> Self {
+        Searcher {
+            limits,
+            table,
+            stop,
+            start: Instant::now(),
+            budget: limits.time_budget(color),
+            nodes: 0,
+            stopped: false,
+            pv_table: vec![[Move::NULL; MAX_PLY]; MAX_PLY],
+            pv_length: [0; MAX_PLY + 1],
+            evaluator: Evaluator::new(),
+        }
+    }
+
+    fn negamax(
+        &mut self,
+        state: &mut State,
+        mut depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        self.pv_length[ply] = 0;
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && state.halfmove_clock() >= 100 {
+            return 0;
+        }
+        // Never stop in check: the static evaluation cannot tell a mate.
+        let in_check = state.in_check();
+        if in_check {
+            depth += 1;
+        }
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(state, ply, alpha, beta);
+        }
+
+        let entry = self.table.probe(state.hash(), ply);
+        // Cutting off at a node inside the window would cut the principal
+        // variation short.
+        let pv_node = beta - alpha > 1;
+        if let Some(entry) = entry.filter(|entry| ply > 0 && !pv_node && entry.depth >= depth) {
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            };
+            if cutoff {
+                return entry.score;
+            }
+        }
+
+        let mut moves = state.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        // The best move found here before, typically by the previous iteration,
+        // goes first.
+        order_moves(state, &mut moves, entry.and_then(|entry| entry.best_move));
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for &chess_move in &moves {
+            state.make_move(chess_move);
+            let score = -self.negamax(state, depth - 1, ply + 1, -beta, -alpha);
+            state.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(chess_move);
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, chess_move);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.table.store(
+            state.hash(),
+            ply,
+            Entry {
+                // Failing low, every move was refuted and none is known best.
+                best_move: best_move.filter(|_| bound != Bound::Upper),
+                score: best_score,
+                depth,
+                bound,
+            },
+        );
+        best_score
+    }
+
+    /// Searches captures and promotions until the position is quiet, so the
+    /// static evaluation is never taken in the middle of an exchange. The side
+    /// to move may stand pat instead of capturing, except when in check, where
+    /// every evasion is searched.
+    fn quiescence(&mut self, state: &mut State, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        self.pv_length[ply] = 0;
+        if self.should_stop() {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.evaluator.evaluate(state);
+        }
+
+        let in_check = state.in_check();
+        let stand_pat = self.evaluator.evaluate(state);
+        let mut moves = if in_check {
+            let moves = state.legal_moves();
+            if moves.is_empty() {
+                return -MATE + ply as i32;
+            }
+            moves
+        } else {
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            state.legal_captures()
+        };
+        order_moves(state, &mut moves, None);
+
+        let mut best_score = if in_check { -INFINITY } else { stand_pat };
+        for &chess_move in &moves {
+            if !in_check && !chess_move.is_promotion() {
+                let victim = state
+                    .piece_at(chess_move.to())
+                    .map_or(PieceType::Pawn, |(piece, _)| piece);
+                if stand_pat + SEE_VALUES[victim as usize] + DELTA_MARGIN <= alpha {
+                    continue;
+                }
+            }
+            // Captures that lose material in the exchange are not worth it.
+            if !in_check && !state.see_ge(chess_move, 0) {
+                continue;
+            }
+
+            state.make_move(chess_move);
+            let score = -self.quiescence(state, ply + 1, -beta, -alpha);
+            state.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        best_score
+    }
+
+    fn update_pv(&mut self, ply: usize, chess_move: Move) {
+        let child_length = self.pv_length[ply + 1];
+        let (current, below) = self.pv_table.split_at_mut(ply + 1);
+        current[ply][0] = chess_move;
+        current[ply][1..=child_length].copy_from_slice(&below[0][..child_length]);
+        self.pv_length[ply] = child_length + 1;
+    }
+
+    fn principal_variation(&self) -> Vec<Move> {
+        self.pv_table[0][..self.pv_length[0]].to_vec()
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        self.stopped = self.stop.load(Ordering::Relaxed)
+            || self.limits.nodes.is_some_and(|nodes| self.nodes > nodes)
+            || (self.nodes.is_multiple_of(CHECK_INTERVAL)
+                && self
+                    .budget
+                    .is_some_and(|budget| self.start.elapsed() >= budget));
+        self.stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::evaluate;
+
+    fn search_fen(fen: &str, limits: SearchLimits) -> (Option<Move>, Vec<SearchInfo>) {
+        let mut state = State::from_fen(fen).unwrap();
+        let mut infos = Vec::new();
+        let table = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let best_move = search(&mut state, &limits, &table, &stop, &mut |info| {
+            infos.push(info.clone())
+        });
+        assert_eq!(state, State::from_fen(fen).unwrap());
+        (best_move, infos)
+    }
+
+    fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_score_conversion() {
+        assert_eq!(Score::from_internal(35), Score::Centipawns(35));
+        assert_eq!(Score::from_internal(MATE - 1), Score::Mate(1));
+        assert_eq!(Score::from_internal(MATE - 5), Score::Mate(3));
+        assert_eq!(Score::from_internal(-MATE), Score::Mate(0));
+        assert_eq!(Score::from_internal(-MATE + 4), Score::Mate(-2));
+    }
+
+    #[test]
+    fn test_finds_mate_in_one() {
+        let (best_move, infos) = search_fen("7k/8/5KQ1/8/8/8/8/8 w - - 0 1", depth(3));
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, Score::Mate(1));
+        assert_eq!(best_move.unwrap().to_string(), last.pv[0].to_string());
+        let mut state = State::from_fen("7k/8/5KQ1/8/8/8/8/8 w - - 0 1").unwrap();
+        state.make_move(best_move.unwrap());
+        assert!(state.legal_moves().is_empty() && state.in_check());
+    }
+
+    #[test]
+    fn test_finds_mate_in_two() {
+        // Back rank mate: 1. Re8+ Rxe8 2. Rxe8#.
+        let fen = "3r2k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1";
+        let (best_move, infos) = search_fen(
+            fen,
+            SearchLimits {
+                mate: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(infos.last().unwrap().score, Score::Mate(2));
+        assert_eq!(best_move.unwrap().to_string(), "e2e8");
+    }
+
+    #[test]
+    fn test_wins_material() {
+        let (best_move, infos) = search_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", depth(2));
+        assert_eq!(best_move.unwrap().to_string(), "d1d5");
+        assert_eq!(infos.len(), 2);
+        assert!(matches!(infos[1].score, Score::Centipawns(score) if score > 400));
+    }
+
+    #[test]
+    fn test_quiescence_sees_recaptures() {
+        // Without looking past depth 1, Qxd6 wins a pawn; cxd6 wins the queen.
+        let (best_move, infos) = search_fen("4k3/2p5/3p4/8/8/8/8/3QK3 w - - 0 1", depth(1));
+        assert_ne!(best_move.unwrap().to_string(), "d1d6");
+        assert!(matches!(infos[0].score, Score::Centipawns(score) if score > 600));
+    }
+
+    #[test]
+    fn test_quiescence_resolves_exchanges() {
+        let limits = SearchLimits::default();
+        let table = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let mut searcher = Searcher::new(&limits, &table, &stop, Color::White);
+        // Rxd5 exd5 loses the exchange, so White stands pat.
+        let mut state = State::from_fen("4k3/8/4p3/3n4/8/8/8/3RK2R w - - 0 1").unwrap();
+        assert_eq!(
+            searcher.quiescence(&mut state, 0, -INFINITY, INFINITY),
+            evaluate(&state)
+        );
+        // A hanging knight is taken.
+        let mut state = State::from_fen("4k3/8/8/3n4/8/8/8/3RK2R w - - 0 1").unwrap();
+        let after = State::from_fen("4k3/8/8/3R4/8/8/8/4K2R b - - 0 1").unwrap();
+        assert_eq!(
+            searcher.quiescence(&mut state, 0, -INFINITY, INFINITY),
+            -evaluate(&after)
+        );
+    }
+
+    #[test]
+    fn test_quiescence_in_check_finds_mate() {
+        let limits = SearchLimits::default();
+        let table = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let mut searcher = Searcher::new(&limits, &table, &stop, Color::Black);
+        let mut state = State::from_fen("3R2k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(
+            searcher.quiescence(&mut state, 3, -INFINITY, INFINITY),
+            -MATE + 3
+        );
+    }
+
+    #[test]
+    fn test_reports_every_iteration() {
+        let (_, infos) = search_fen(crate::board::state::START_FEN, depth(3));
+        let depths: Vec<u32> = infos.iter().map(|info| info.depth).collect();
+        assert_eq!(depths, [1, 2, 3]);
+        for info in &infos {
+            assert_eq!(info.pv.len(), info.depth as usize);
+            assert!(info.nodes > 0);
+        }
+        assert!(infos[2].nodes > infos[1].nodes);
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        let (best_move, infos) = search_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", depth(3));
+        assert_eq!(best_move, None);
+        assert!(infos.is_empty());
+    }
+
+    #[test]
+    fn test_stop_flag() {
+        let mut state = State::new();
+        let stop = AtomicBool::new(true);
+        let mut infos = 0;
+        let table = TranspositionTable::new(1);
+        let limits = SearchLimits::default();
+        let best_move = search(&mut state, &limits, &table, &stop, &mut |_| infos += 1);
+        assert!(state.legal_moves().contains(best_move.unwrap()));
+        assert!(infos <= 1);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let limits = SearchLimits {
+            nodes: Some(5_000),
+            ..Default::default()
+        };
+        let (best_move, infos) = search_fen(crate::board::state::START_FEN, limits);
+        assert!(best_move.is_some());
+        assert!(infos.iter().all(|info| info.nodes <= 5_000));
+    }
+
+    #[test]
+    fn test_nodes_per_second() {
+        let info = SearchInfo {
+            depth: 1,
+            score: Score::Centipawns(0),
+            nodes: 3_000,
+            elapsed: Duration::from_millis(1_500),
+            pv: Vec::new(),
+            hashfull: 0,
+        };
+        assert_eq!(info.nodes_per_second(), 2_000);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::board::state::Color;
+use std::time::Duration;
+
+/// The constraints of a `go` command. Every limit is optional; without any the
+/// search runs until it is stopped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub mate: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+    /// Time reserved per move for communication lag between engine and GUI.
+    pub move_overhead: Duration,
+}
+
+// Assumed number of moves left in the game when the clock gives no hint.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+impl SearchLimits {
+    /// How long `color` should think about this move, or `None` when no clock
+    /// or move time constrains the search.
+    pub fn time_budget(&self, color: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(movetime) = self.movetime {
+            return Some(movetime.saturating_sub(self.move_overhead));
+        }
+
+        let (time, increment) = match color {
+            Color::White => (self.wtime?, self.winc.unwrap_or_default()),
+            Color::Black => (self.btime?, self.binc.unwrap_or_default()),
+        };
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let available = time.saturating_sub(self.move_overhead);
+        let budget = available / moves_to_go + increment * 3 / 4;
+        Some(budget.min(available))
+    }
+
+    /// The deepest iteration the search should start, if limited.
+    pub fn max_depth(&self) -> Option<u32> {
+        // A mate in n moves is found at 2n - 1 plies.
+        let mate_depth = self.mate.map(|moves| (moves * 2).saturating_sub(1));
+        match (self.depth, mate_depth) {
+            (Some(depth), Some(mate)) => Some(depth.min(mate)),
+            (depth, mate) => depth.or(mate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_unlimited() {
+        assert_eq!(SearchLimits::default().time_budget(Color::White), None);
+        let limits = SearchLimits {
+            infinite: true,
+            wtime: Some(ms(1000)),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(Color::White), None);
+    }
+
+    #[test]
+    fn test_movetime() {
+        let limits = SearchLimits {
+            movetime: Some(ms(1000)),
+            move_overhead: ms(30),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(Color::Black), Some(ms(970)));
+    }
+
+    #[test]
+    fn test_clock() {
+        let limits = SearchLimits {
+            wtime: Some(ms(60_000)),
+            btime: Some(ms(30_000)),
+            winc: Some(ms(1000)),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(Color::White), Some(ms(2750)));
+        assert_eq!(limits.time_budget(Color::Black), Some(ms(1000)));
+
+        let limits = SearchLimits {
+            wtime: Some(ms(10_000)),
+            movestogo: Some(5),
+            move_overhead: ms(100),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(Color::White), Some(ms(1980)));
+        assert_eq!(limits.time_budget(Color::Black), None);
+    }
+
+    #[test]
+    fn test_budget_never_exceeds_clock() {
+        let limits = SearchLimits {
+            btime: Some(ms(100)),
+            binc: Some(ms(1000)),
+            move_overhead: ms(50),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(Color::Black), Some(ms(50)));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let limits = |depth, mate| SearchLimits {
+            depth,
+            mate,
+            ..Default::default()
+        };
+        assert_eq!(limits(None, None).max_depth(), None);
+        assert_eq!(limits(Some(7), None).max_depth(), Some(7));
+        assert_eq!(limits(None, Some(3)).max_depth(), Some(5));
+        assert_eq!(limits(Some(4), Some(3)).max_depth(), Some(4));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::board::moves::{Move, MoveList};
+use crate::board::state::{PieceType, State};
+use std::cmp::Reverse;
+
+// Ahead of every capture.
+const PV_MOVE: i32 = 1_000_000;
+const CAPTURE: i32 = 10_000;
+const PROMOTION: i32 = 5_000;
+// Captures that lose material in the exchange come after the quiet moves.
+const LOSING_CAPTURE: i32 = -10_000;
+
+/// Sorts `moves` so the most promising come first: `pv_move`, then captures
+/// that do not lose material by MVV-LVA, then promotions, then quiet moves in
+/// generation order, and losing captures last.
+pub fn order_moves(state: &State, moves: &mut MoveList, pv_move: Option<Move>) {
+    moves
+        .as_mut_slice()
+        .sort_by_cached_key(|&chess_move| Reverse(move_score(state, chess_move, pv_move)));
+}
+
+fn move_score(state: &State, chess_move: Move, pv_move: Option<Move>) -> i32 {
+    if Some(chess_move) == pv_move {
+        return PV_MOVE;
+    }
+    let mut score = 0;
+    if chess_move.is_capture() {
+        score += mvv_lva(state, chess_move);
+        score += if state.see_ge(chess_move, 0) {
+            CAPTURE
+        } else {
+            LOSING_CAPTURE
+        };
+    }
+    if let Some(piece) = chess_move.promotion_piece() {
+        score += PROMOTION + piece as i32;
+    }
+    score
+}
+
+/// Most valuable victim, least valuable attacker: a pawn taking a queen is
+/// tried before a queen taking a pawn.
+pub fn mvv_lva(state: &State, chess_move: Move) -> i32 {
+    let victim = if chess_move.is_en_passant() {
+        PieceType::Pawn
+    } else {
+        state
+            .piece_at(chess_move.to())
+            .map_or(PieceType::Pawn, |(piece, _)| piece)
+    };
+    let (attacker, _) = state
+        .piece_at(chess_move.from())
+        .expect("a move starts on an occupied square");
+    victim as i32 * 8 - attacker as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_moves() {
+        // The queen on d5 can be taken by the pawn, knight or rook; the rook on
+        // a8 by the rook.
+        let state = State::from_fen("r3k3/8/8/3q4/4P3/2N5/8/R2RK3 w - - 0 1").unwrap();
+        let mut moves = state.legal_moves();
+        order_moves(&state, &mut moves, None);
+        let first: Vec<String> = moves.iter().take(4).map(Move::to_string).collect();
+        assert_eq!(first, ["e4d5", "c3d5", "d1d5", "a1a8"]);
+        assert!(moves.iter().skip(4).all(|m| !m.is_capture()));
+
+        let pv_move = Move::from_uci("e1f2", &state).unwrap();
+        order_moves(&state, &mut moves, Some(pv_move));
+        assert_eq!(moves.iter().next(), Some(&pv_move));
+    }
+
+    #[test]
+    fn test_losing_captures_last() {
+        // Qxd5 loses the queen to exd5.
+        let state = State::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mut moves = state.legal_moves();
+        order_moves(&state, &mut moves, None);
+        assert_eq!(moves.as_slice().last().unwrap().to_string(), "d1d5");
+    }
+
+    #[test]
+    fn test_promotions_before_quiet_moves() {
+        let state = State::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut moves = state.legal_moves();
+        order_moves(&state, &mut moves, None);
+        assert_eq!(moves.iter().next().unwrap().to_string(), "b7b8q");
+        assert!(moves.iter().take(4).all(|m| m.is_promotion()));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::board::moves::Move;
+use crate::board::state::State;
+use crate::search::{search, SearchInfo, SearchLimits, TranspositionTable};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Runs searches in the background so the protocol loop keeps reading
+/// commands, and `stop` can interrupt a search that is in progress. Owns the
+/// transposition table the searches share.
+pub struct SearchThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    table: Arc<TranspositionTable>,
+}
+
+impl SearchThread {
+    pub fn new() -> Self {
+        SearchThread {
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: None,
+            table: Arc::new(TranspositionTable::default()),
+        }
+    }
+
+    /// Searches `state` on a new thread, passing progress to `on_info` and the
+    /// best move to `on_finish` once the search ends. An infinite search only
+    /// ends when it is stopped. A search that is still running is stopped first.
+    pub fn start<I, F>(
+        &mut self,
+        mut state: State,
+        limits: SearchLimits,
+        mut on_info: I,
+        on_finish: F,
+    ) where
+        I: FnMut(&SearchInfo) + Send + 'static,
+        F: FnOnce(Option<Move>) + Send + 'static,
+    {
+        self.stop();
+        self.stop.store(false, Ordering::SeqCst);
+        let stop = Arc::clone(&self.stop);
+        let table = Arc::clone(&self.table);
+        self.handle = Some(thread::spawn(move || {
+            let best_move = search(&mut state, &limits, &table, &stop, &mut on_info);
+            while limits.infinite && !stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            on_finish(best_move);
+        }));
+    }
+
+    /// Interrupts the running search, if any, and waits for it to report.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.wait();
+    }
+
+    /// Waits for the running search, if any, to end on its own.
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("search thread panicked");
+        }
+    }
+
+    /// Replaces the transposition table with an empty one of `megabytes`,
+    /// stopping the running search first.
+    pub fn resize_table(&mut self, megabytes: usize) {
+        self.stop();
+        self.table = Arc::new(TranspositionTable::new(megabytes));
+    }
+
+    /// Empties the transposition table, stopping the running search first.
+    pub fn clear_table(&mut self) {
+        self.stop();
+        self.table.clear();
+    }
+}
+
+impl Default for SearchThread {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for SearchThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,340 @@
+use crate::board::moves::Move;
+use crate::search::{MATE, MAX_PLY};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 65536;
+
+// Scores beyond this are mates, stored relative to the entry's position.
+const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// An age is six bits wide.
+const AGE_MASK: u8 = 0x3F;
+
+/// Which side of the true score a stored score lies on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact: every move was searched inside the window.
+    Exact,
+    /// The search failed high; the true score is at least this.
+    Lower,
+    /// No move reached alpha; the true score is at most this.
+    Upper,
+}
+
+/// What the table remembers about a searched position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+/// A hash table of search results shared by every search, sized in megabytes.
+///
+/// Each bucket holds two entries: one keeps the deepest result, the other the
+/// most recent one. Entries are two atomic words, the data and the key XORed
+/// with the data, so a search can read and write the table without locks: an
+/// entry torn by a concurrent write fails the key check and is ignored.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: AtomicU8,
+}
+
+#[derive(Default)]
+struct Bucket {
+    depth_preferred: Slot,
+    always_replace: Slot,
+}
+
+#[derive(Default)]
+struct Slot {
+    checked_key: AtomicU64,
+    data: AtomicU64,
+}
+
+// Data layout, from the low bits: move (16), score (16), depth (8), bound (2),
+// age (6). A data word of zero is an empty slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Data {
+    best_move: u16,
+    score: i16,
+    depth: u8,
+    bound: Bound,
+    age: u8,
+}
+
+impl Data {
+    fn pack(self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        self.best_move as u64
+            | (self.score as u16 as u64) << 16
+            | (self.depth as u64) << 32
+            | bound << 40
+            | ((self.age & AGE_MASK) as u64) << 42
+    }
+
+    fn unpack(bits: u64) -> Option<Data> {
+        let bound = match (bits >> 40) & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        Some(Data {
+            best_move: bits as u16,
+            score: (bits >> 16) as u16 as i16,
+            depth: (bits >> 32) as u8,
+            bound,
+            age: (bits >> 42) as u8 & AGE_MASK,
+        })
+    }
+}
+
+impl Slot {
+    fn load(&self, key: u64) -> Option<Data> {
+        let data = self.data.load(Ordering::Relaxed);
+        if self.checked_key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Data::unpack(data)
+    }
+
+    fn load_any(&self) -> Option<Data> {
+        Data::unpack(self.data.load(Ordering::Relaxed))
+    }
+
+    fn key(&self) -> u64 {
+        self.checked_key.load(Ordering::Relaxed) ^ self.data.load(Ordering::Relaxed)
+    }
+
+    fn store(&self, key: u64, data: Data) {
+        let data = data.pack();
+        self.checked_key.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.checked_key.store(0, Ordering::Relaxed);
+        self.data.store(0, Ordering::Relaxed);
+    }
+}
+
+impl TranspositionTable {
+    /// A table taking up at most `megabytes` of memory, but at least one bucket.
+    pub fn new(megabytes: usize) -> Self {
+        let count = (megabytes * 1024 * 1024 / size_of::<Bucket>()).max(1);
+        TranspositionTable {
+            buckets: (0..count).map(|_| Bucket::default()).collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    /// Forgets every stored position.
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            bucket.depth_preferred.clear();
+            bucket.always_replace.clear();
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the entries stored so far as belonging to an earlier search, so
+    /// that they are replaced first.
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) & AGE_MASK, Ordering::Relaxed);
+    }
+
+    /// The entry stored for `key`, with mate scores made relative to the
+    /// position `ply` plies below the root.
+    pub fn probe(&self, key: u64, ply: usize) -> Option<Entry> {
+        let bucket = self.bucket(key);
+        let data = bucket
+            .depth_preferred
+            .load(key)
+            .or_else(|| bucket.always_replace.load(key))?;
+        Some(Entry {
+            best_move: (data.best_move != 0).then(|| Move::from_bits(data.best_move)),
+            score: score_from_table(data.score as i32, ply),
+            depth: data.depth as u32,
+            bound: data.bound,
+        })
+    }
+
+    /// Stores the result of searching the position `key` at `ply` plies below
+    /// the root. It goes in the depth-preferred slot if it is at least as deep
+    /// as the entry there or that entry is from an earlier search, and in the
+    /// always-replace slot otherwise.
+    pub fn store(&self, key: u64, ply: usize, entry: Entry) {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+        let mut data = Data {
+            best_move: entry.best_move.map_or(0, Move::to_bits),
+            score: score_to_table(entry.score, ply) as i16,
+            depth: entry.depth.min(u8::MAX as u32) as u8,
+            bound: entry.bound,
+            age,
+        };
+
+        let slot = match bucket.depth_preferred.load_any() {
+            Some(existing)
+                if existing.age == age
+                    && existing.depth > data.depth
+                    && bucket.depth_preferred.key() != key =>
+            {
+                &bucket.always_replace
+            }
+            _ => &bucket.depth_preferred,
+        };
+        // A result without a move should not erase the move known for the
+        // same position.
+        if data.best_move == 0 {
+            if let Some(existing) = slot.load(key) {
+                data.best_move = existing.best_move;
+            }
+        }
+        slot.store(key, data);
+    }
+
+    /// How full the table is in permille, sampled from the first thousand
+    /// buckets and counting entries of the current search only.
+    pub fn hashfull(&self) -> u32 {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = &self.buckets[..self.buckets.len().min(1000)];
+        let used = sample
+            .iter()
+            .flat_map(|bucket| [&bucket.depth_preferred, &bucket.always_replace])
+            .filter(|slot| slot.load_any().is_some_and(|data| data.age == age))
+            .count();
+        (used * 1000 / (sample.len() * 2)) as u32
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        // Maps the key onto the buckets without a division.
+        let index = ((key as u128 * self.buckets.len() as u128) >> 64) as usize;
+        &self.buckets[index]
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+// Mate scores count plies from the root, but an entry can be found again at a
+// different ply, so they are stored counting from the entry's position.
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::state::State;
+
+    fn entry(depth: u32, score: i32) -> Entry {
+        let state = State::new();
+        Entry {
+            best_move: Some(Move::from_uci("e2e4", &state).unwrap()),
+            score,
+            depth,
+            bound: Bound::Exact,
+        }
+    }
+
+    #[test]
+    fn test_store_and_probe() {
+        let table = TranspositionTable::new(1);
+        assert_eq!(table.probe(0x1234, 0), None);
+        table.store(0x1234, 0, entry(5, -42));
+        assert_eq!(table.probe(0x1234, 0), Some(entry(5, -42)));
+        assert_eq!(table.probe(0x1235, 0), None);
+
+        let upper = Entry {
+            best_move: None,
+            bound: Bound::Upper,
+            ..entry(6, 17)
+        };
+        table.store(0x1234, 0, upper);
+        // The move of the earlier entry is kept.
+        assert_eq!(
+            table.probe(0x1234, 0),
+            Some(Entry {
+                best_move: entry(0, 0).best_move,
+                ..upper
+            })
+        );
+
+        table.clear();
+        assert_eq!(table.probe(0x1234, 0), None);
+    }
+
+    #[test]
+    fn test_mate_scores_are_relative_to_the_position() {
+        let table = TranspositionTable::new(1);
+        // Mate in three plies from the root, found at ply 1.
+        table.store(7, 1, entry(4, MATE - 3));
+        assert_eq!(table.probe(7, 1).unwrap().score, MATE - 3);
+        // The same position at ply 5 is mated two plies later from the root.
+        assert_eq!(table.probe(7, 5).unwrap().score, MATE - 7);
+
+        table.store(8, 2, entry(4, -MATE + 4));
+        assert_eq!(table.probe(8, 0).unwrap().score, -MATE + 2);
+    }
+
+    #[test]
+    fn test_replacement() {
+        // A single bucket, so every key collides.
+        let table = TranspositionTable::new(0);
+        table.store(1, 0, entry(8, 1));
+        table.store(2, 0, entry(3, 2));
+        // The deep entry stays, the shallow one goes to the other slot.
+        assert_eq!(table.probe(1, 0).unwrap().depth, 8);
+        assert_eq!(table.probe(2, 0).unwrap().depth, 3);
+        table.store(3, 0, entry(2, 3));
+        assert_eq!(table.probe(1, 0).unwrap().depth, 8);
+        assert_eq!(table.probe(2, 0), None);
+        assert_eq!(table.probe(3, 0).unwrap().depth, 2);
+
+        // Entries of an earlier search are replaced regardless of depth.
+        table.new_search();
+        table.store(4, 0, entry(1, 4));
+        assert_eq!(table.probe(1, 0), None);
+        assert_eq!(table.probe(4, 0).unwrap().depth, 1);
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let table = TranspositionTable::new(1);
+        assert_eq!(table.hashfull(), 0);
+        for key in 0..u16::MAX as u64 {
+            table.store(key.wrapping_mul(0x9E37_79B9_7F4A_7C15), 0, entry(1, 0));
+        }
+        assert!(table.hashfull() > 300);
+        table.new_search();
+        assert_eq!(table.hashfull(), 0);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::board::moves::Move;
+use crate::board::perft::print_divide;
+use crate::board::state::State;
+use crate::search::{Score, SearchInfo, SearchLimits, SearchThread, DEFAULT_HASH_MB, MAX_HASH_MB};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_MOVE_OVERHEAD: u64 = 30;
+const MAX_MOVE_OVERHEAD: u64 = 5000;
+
+/// The Universal Chess Interface front end. Commands are read line by line;
+/// searches run on a separate thread, which writes `bestmove` when done.
+pub struct Uci<W: Write + Send + 'static> {
+    state: State,
+    search: SearchThread,
+    out: Arc<Mutex<W>>,
+    move_overhead: Duration,
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    pub fn new(out: W) -> Self {
+        Uci {
+            state: State::new(),
+            search: SearchThread::new(),
+            out: Arc::new(Mutex::new(out)),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+        }
+    }
+
+    /// Executes one command line. Returns `false` once the engine should exit.
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                self.send(&format!("id name pato {}", env!("CARGO_PKG_VERSION")));
+                self.send("id author the pato developers");
+                self.send(&format!(
+                    "option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}"
+                ));
+                self.send(&format!(
+                    "option name Move Overhead type spin default {DEFAULT_MOVE_OVERHEAD} min 0 max {MAX_MOVE_OVERHEAD}"
+                ));
+                #[cfg(feature = "nnue")]
+                self.send("option name EvalFile type string default <empty>");
+                self.send("uciok");
+            }
+            Some("isready") => self.send("readyok"),
+            Some("ucinewgame") => {
+                self.search.clear_table();
+                self.state = State::new();
+            }
+            Some("position") => {
+                self.search.stop();
+                match parse_position(tokens) {
+                    Ok(state) => self.state = state,
+                    Err(error) => self.send(&format!("info string {error}")),
+                }
+            }
+            Some("go") => self.go(tokens),
+            Some("stop") => self.search.stop(),
+            Some("setoption") => self.set_option(tokens),
+            Some("quit") => {
+                self.search.stop();
+                return false;
+            }
+            Some("ponderhit") | None => {}
+            Some(command) => self.send(&format!("info string unknown command '{command}'")),
+        }
+        true
+    }
+
+    fn go<'a>(&mut self, mut tokens: impl Iterator<Item = &'a str>) {
+        let mut limits = SearchLimits {
+            move_overhead: self.move_overhead,
+            ..Default::default()
+        };
+        while let Some(token) = tokens.next() {
+            let mut number = || tokens.next().and_then(|value| value.parse::<i64>().ok());
+            let millis =
+                |value: Option<i64>| value.map(|ms| Duration::from_millis(ms.max(0) as u64));
+            match token {
+                "depth" => limits.depth = number().map(|depth| depth.max(1) as u32),
+                "nodes" => limits.nodes = number().map(|nodes| nodes.max(1) as u64),
+                "mate" => limits.mate = number().map(|moves| moves.max(1) as u32),
+                "movetime" => limits.movetime = millis(number()),
+                "wtime" => limits.wtime = millis(number()),
+                "btime" => limits.btime = millis(number()),
+                "winc" => limits.winc = millis(number()),
+                "binc" => limits.binc = millis(number()),
+                "movestogo" => limits.movestogo = number().map(|moves| moves.max(1) as u32),
+                "infinite" => limits.infinite = true,
+                "perft" => {
+                    let depth = number().unwrap_or(1).max(1) as u32;
+                    let mut out = self.out.lock().unwrap();
+                    print_divide(&mut self.state, depth, &mut *out).expect("writing output");
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        let info_out = Arc::clone(&self.out);
+        let out = Arc::clone(&self.out);
+        self.search.start(
+            self.state.clone(),
+            limits,
+            move |info: &SearchInfo| {
+                let mut out = info_out.lock().unwrap();
+                writeln!(out, "{}", info_line(info)).expect("writing output");
+                out.flush().expect("writing output");
+            },
+            move |best_move: Option<Move>| {
+                let best_move = best_move.unwrap_or(Move::NULL);
+                let mut out = out.lock().unwrap();
+                writeln!(out, "bestmove {best_move}").expect("writing output");
+                out.flush().expect("writing output");
+            },
+        );
+    }
+
+    fn set_option<'a>(&mut self, tokens: impl Iterator<Item = &'a str>) {
+        let tokens: Vec<&str> = tokens.collect();
+        let value_index = tokens.iter().position(|&token| token == "value");
+        let name = tokens[..value_index.unwrap_or(tokens.len())]
+            .iter()
+            .skip_while(|&&token| token == "name")
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let value = value_index.map(|index| tokens[index + 1..].join(" "));
+
+        match (name.to_lowercase().as_str(), value) {
+            ("hash", Some(value)) => match value.parse::<usize>() {
+                Ok(megabytes) => self.search.resize_table(megabytes.clamp(1, MAX_HASH_MB)),
+                Err(_) => self.send(&format!("info string invalid Hash '{value}'")),
+            },
+            ("move overhead", Some(value)) => match value.parse::<u64>() {
+                Ok(millis) => {
+                    self.move_overhead = Duration::from_millis(millis.min(MAX_MOVE_OVERHEAD))
+                }
+                Err(_) => self.send(&format!("info string invalid Move Overhead '{value}'")),
+            },
+            #[cfg(feature = "nnue")]
+            ("evalfile", Some(value)) => self.load_network(&value),
+            _ => self.send(&format!("info string unknown option '{name}'")),
+        }
+    }
+
+    /// Loads the network in the file at `path` for the current and later
+    /// positions, or goes back to the hand-crafted evaluation when no path is
+    /// given. A file that cannot be loaded leaves the evaluation as it was.
+    #[cfg(feature = "nnue")]
+    fn load_network(&mut self, path: &str) {
+        use crate::eval::nnue::{self, Network};
+
+        let network = match path {
+            "" | "<empty>" => None,
+            path => match Network::load(path) {
+                Ok(network) => Some(Arc::new(network)),
+                Err(error) => {
+                    self.send(&format!(
+                        "info string cannot load EvalFile '{path}': {error}"
+                    ));
+                    return;
+                }
+            },
+        };
+        self.search.stop();
+        nnue::set_network(network.clone());
+        self.state.set_network(network);
+    }
+
+    fn send(&self, line: &str) {
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{line}").expect("writing output");
+        out.flush().expect("writing output");
+    }
+}
+
+/// The `info` line describing a completed search iteration.
+fn info_line(info: &SearchInfo) -> String {
+    let score = match info.score {
+        Score::Centipawns(centipawns) => format!("cp {centipawns}"),
+        Score::Mate(moves) => format!("mate {moves}"),
+    };
+    let pv: Vec<String> = info.pv.iter().map(Move::to_string).collect();
+    format!(
+        "info depth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+        info.depth,
+        score,
+        info.nodes,
+        info.nodes_per_second(),
+        info.hashfull,
+        info.elapsed.as_millis(),
+        pv.join(" ")
+    )
+}
+
+/// Parses the arguments of `position`: `startpos` or `fen <fen>`, optionally
+/// followed by `moves` and a list of moves in UCI notation.
+fn parse_position<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<State, String> {
+    let tokens: Vec<&str> = tokens.collect();
+    let moves_index = tokens.iter().position(|&token| token == "moves");
+    let setup = &tokens[..moves_index.unwrap_or(tokens.len())];
+
+    let mut state = match setup.first() {
+        Some(&"startpos") => State::new(),
+        Some(&"fen") => State::from_fen(&setup[1..].join(" "))
+            .map_err(|error| format!("invalid FEN: {error}"))?,
+        _ => return Err("expected 'startpos' or 'fen'".to_string()),
+    };
+
+    for &text in moves_index.map_or(&[][..], |index| &tokens[index + 1..]) {
+        let chess_move = Move::from_uci(text, &state)
+            .ok()
+            .filter(|&chess_move| state.legal_moves().contains(chess_move))
+            .ok_or_else(|| format!("illegal move '{text}' in {}", state.to_fen()))?;
+        state.make_move(chess_move);
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::SharedBuffer;
+
+    fn engine() -> (Uci<SharedBuffer>, SharedBuffer) {
+        let buffer = SharedBuffer::default();
+        (Uci::new(buffer.clone()), buffer)
+    }
+
+    #[test]
+    fn test_handshake() {
+        let (mut uci, out) = engine();
+        assert!(uci.handle_command("uci"));
+        assert!(uci.handle_command("isready"));
+        let lines = out.lines();
+        assert!(lines[0].starts_with("id name pato"));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("option name Move Overhead")));
+        assert_eq!(lines[lines.len() - 2], "uciok");
+        assert_eq!(lines[lines.len() - 1], "readyok");
+    }
+
+    #[test]
+    fn test_position() {
+        let state = parse_position("startpos moves e2e4 e7e5 g1f3".split_whitespace()).unwrap();
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let state = parse_position(format!("fen {fen} moves e2e4").split_whitespace()).unwrap();
+        assert_eq!(state.to_fen(), "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1");
+
+        let state = parse_position(format!("fen {fen}").split_whitespace()).unwrap();
+        assert_eq!(state.to_fen(), fen);
+
+        assert!(parse_position("startpos moves e2e5".split_whitespace()).is_err());
+        assert!(parse_position("fen 8/8 w - - 0 1".split_whitespace()).is_err());
+        assert!(parse_position("nowhere".split_whitespace()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_position_keeps_previous() {
+        let (mut uci, out) = engine();
+        uci.handle_command("position startpos moves e2e4");
+        uci.handle_command("position startpos moves e2e4 e2e4");
+        assert_eq!(
+            uci.state.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert!(out.lines()[0].starts_with("info string illegal move 'e2e4'"));
+    }
+
+    #[test]
+    fn test_go_reports_bestmove() {
+        let (mut uci, out) = engine();
+        uci.handle_command("position fen 4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        uci.handle_command("go depth 1");
+        uci.search.wait();
+        let lines = out.lines();
+        let best = lines.last().unwrap().strip_prefix("bestmove ").unwrap();
+        let state = State::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let best = Move::from_uci(best, &state).unwrap();
+        assert!(state.legal_moves().contains(best));
+    }
+
+    #[test]
+    fn test_go_reports_info() {
+        let (mut uci, out) = engine();
+        uci.handle_command("position fen 7k/8/5KQ1/8/8/8/8/8 w - - 0 1");
+        uci.handle_command("go depth 2");
+        uci.search.wait();
+        let lines = out.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("info depth 1 score mate 1 nodes "));
+        assert!(lines[1].starts_with("info depth 2 score mate 1 nodes "));
+        assert!(lines[1].contains(" nps "));
+        let pv = lines[1].split(" pv ").nth(1).unwrap();
+        assert_eq!(lines[2], format!("bestmove {pv}"));
+    }
+
+    #[test]
+    fn test_info_line() {
+        let state = State::new();
+        let info = SearchInfo {
+            depth: 3,
+            score: Score::Mate(-2),
+            nodes: 500,
+            elapsed: Duration::from_millis(250),
+            pv: vec![Move::from_uci("e2e4", &state).unwrap()],
+            hashfull: 12,
+        };
+        assert_eq!(
+            info_line(&info),
+            "info depth 3 score mate -2 nodes 500 nps 2000 hashfull 12 time 250 pv e2e4"
+        );
+    }
+
+    #[test]
+    fn test_go_without_legal_moves() {
+        let (mut uci, out) = engine();
+        uci.handle_command("position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        uci.handle_command("go movetime 10");
+        uci.search.wait();
+        assert_eq!(out.lines(), ["bestmove 0000"]);
+    }
+
+    #[test]
+    fn test_infinite_search_waits_for_stop() {
+        let (mut uci, out) = engine();
+        uci.handle_command("go infinite");
+        std::thread::sleep(Duration::from_millis(20));
+        let bestmove = |line: &String| line.starts_with("bestmove ");
+        assert!(!out.lines().iter().any(bestmove));
+        uci.handle_command("stop");
+        let lines = out.lines();
+        assert_eq!(lines.iter().filter(|line| bestmove(line)).count(), 1);
+        assert!(bestmove(lines.last().unwrap()));
+    }
+
+    #[test]
+    fn test_go_perft() {
+        let (mut uci, out) = engine();
+        uci.handle_command("go perft 2");
+        assert_eq!(out.lines().last().unwrap(), "Nodes searched: 400");
+    }
+
+    #[test]
+    fn test_setoption() {
+        let (mut uci, out) = engine();
+        uci.handle_command("setoption name Move Overhead value 120");
+        assert_eq!(uci.move_overhead, Duration::from_millis(120));
+        uci.handle_command("setoption name Ponder value true");
+        assert_eq!(out.lines(), ["info string unknown option 'Ponder'"]);
+    }
+
+    #[cfg(feature = "nnue")]
+    #[test]
+    fn test_missing_eval_file() {
+        let (mut uci, out) = engine();
+        uci.handle_command("setoption name EvalFile value /nonexistent/pato.nnue");
+        assert!(out.lines()[0].starts_with("info string cannot load EvalFile"));
+        assert_eq!(uci.state.accumulator(), None);
+    }
+
+    #[test]
+    fn test_quit() {
+        let (mut uci, _) = engine();
+        assert!(uci.handle_command("ucinewgame"));
+        assert!(!uci.handle_command("quit"));
+    }
+}
@@ -0,0 +1,555 @@
+use crate::board::moves::Move;
+use crate::board::state::{Color, State};
+use crate::search::{Score, SearchInfo, SearchLimits, SearchThread, MAX_HASH_MB};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The Chess Engine Communication Protocol (xboard/WinBoard) front end.
+///
+/// Unlike UCI, the engine keeps track of the game itself: it plays the moves it
+/// finds on its own board and tells the GUI with `move`. The board is shared
+/// with the search thread so a finished search can play its move right away.
+pub struct XBoard<W: Write + Send + 'static> {
+    game: Arc<Mutex<Game>>,
+    search: SearchThread,
+    out: Arc<Mutex<W>>,
+    // The side the engine plays, `None` in force mode.
+    engine_color: Option<Color>,
+    post: bool,
+    time_control: TimeControl,
+    move_time: Option<Duration>,
+    max_depth: Option<u32>,
+    engine_clock: Option<Duration>,
+}
+
+struct Game {
+    state: State,
+    // Bumped whenever the board changes under a running search, so its move
+    // is dropped instead of being played on the wrong position.
+    generation: u64,
+    // How many moves `state` had when the time control session began, at
+    // `new`, `setboard` or `level`.
+    session_start: usize,
+}
+
+impl Game {
+    /// How many moves the side to move has played in this session.
+    fn session_moves(&self) -> u32 {
+        let plies = self
+            .state
+            .moves_played()
+            .count()
+            .saturating_sub(self.session_start);
+        (plies / 2) as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TimeControl {
+    moves_per_session: u32,
+    base: Duration,
+    increment: Duration,
+}
+
+// What xboard itself assumes until it sends `level`: 40 moves in 5 minutes.
+const DEFAULT_TIME_CONTROL: TimeControl = TimeControl {
+    moves_per_session: 40,
+    base: Duration::from_secs(300),
+    increment: Duration::ZERO,
+};
+
+// xboard reports mate scores as 100000 plus the distance in moves.
+const MATE_SCORE: i32 = 100_000;
+
+impl<W: Write + Send + 'static> XBoard<W> {
+    pub fn new(out: W) -> Self {
+        XBoard {
+            game: Arc::new(Mutex::new(Game {
+                state: State::new(),
+                generation: 0,
+                session_start: 0,
+            })),
+            search: SearchThread::new(),
+            out: Arc::new(Mutex::new(out)),
+            engine_color: Some(Color::Black),
+            post: false,
+            time_control: DEFAULT_TIME_CONTROL,
+            move_time: None,
+            max_depth: None,
+            engine_clock: None,
+        }
+    }
+
+    /// Executes one command line. Returns `false` once the engine should exit.
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return true;
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        let argument = |index: usize| arguments.get(index).copied().unwrap_or("");
+
+        match command {
+            "protover" => self.send(&format!(
+                "feature myname=\"pato {}\" setboard=1 usermove=1 ping=1 memory=1 \
+                 playother=0 san=0 colors=0 sigint=0 sigterm=0 analyze=0 done=1",
+                env!("CARGO_PKG_VERSION")
+            )),
+            "new" => {
+                self.reset(State::new());
+                self.search.clear_table();
+                self.engine_color = Some(Color::Black);
+                self.max_depth = None;
+                self.move_time = None;
+                self.engine_clock = None;
+            }
+            "force" => {
+                self.abandon_search();
+                self.engine_color = None;
+            }
+            "go" => {
+                self.abandon_search();
+                self.engine_color = Some(self.game.lock().unwrap().state.side_to_move());
+                self.think();
+            }
+            "setboard" => match State::from_fen(&arguments.join(" ")) {
+                Ok(state) => self.reset(state),
+                Err(error) => self.send(&format!("tellusererror Illegal position: {error}")),
+            },
+            "usermove" => self.user_move(argument(0)),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "level" => match parse_level(&arguments) {
+                Some(time_control) => {
+                    let mut game = self.game.lock().unwrap();
+                    game.session_start = game.state.moves_played().count();
+                    drop(game);
+                    self.time_control = time_control;
+                    self.move_time = None;
+                }
+                None => self.error("invalid time control", line),
+            },
+            "st" => match argument(0).parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => {
+                    self.move_time = Some(Duration::from_secs_f64(seconds))
+                }
+                _ => self.error("invalid time per move", line),
+            },
+            "sd" => match argument(0).parse::<u32>() {
+                Ok(depth) if depth > 0 => self.max_depth = Some(depth),
+                _ => self.error("invalid depth", line),
+            },
+            "time" => match argument(0).parse::<u64>() {
+                Ok(centiseconds) => {
+                    self.engine_clock = Some(Duration::from_millis(centiseconds * 10))
+                }
+                Err(_) => self.error("invalid time", line),
+            },
+            "memory" => match argument(0).parse::<usize>() {
+                Ok(megabytes) => self.search.resize_table(megabytes.clamp(1, MAX_HASH_MB)),
+                Err(_) => self.error("invalid memory size", line),
+            },
+            "ping" => self.send(&format!("pong {}", argument(0))),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "?" => self.search.stop(),
+            "quit" => {
+                self.abandon_search();
+                return false;
+            }
+            "xboard" | "accepted" | "rejected" | "otim" | "random" | "hard" | "easy"
+            | "computer" | "name" | "rating" | "ics" | "result" | "draw" | "white" | "black" => {}
+            // Protocol version 1 sends moves without the `usermove` prefix.
+            _ if Move::from_uci(command, &self.game.lock().unwrap().state).is_ok() => {
+                self.user_move(command)
+            }
+            _ => self.error("unknown command", line),
+        }
+        true
+    }
+
+    fn user_move(&mut self, text: &str) {
+        let mut game = self.game.lock().unwrap();
+        let legal = Move::from_uci(text, &game.state)
+            .ok()
+            .filter(|&chess_move| game.state.legal_moves().contains(chess_move));
+        let Some(chess_move) = legal else {
+            drop(game);
+            self.send(&format!("Illegal move: {text}"));
+            return;
+        };
+        game.state.make_move(chess_move);
+        game.generation += 1;
+        let result = game_result(&game.state);
+        let engine_to_move = self.engine_color == Some(game.state.side_to_move());
+        drop(game);
+
+        if let Some(result) = result {
+            self.send(result);
+        } else if engine_to_move {
+            self.think();
+        }
+    }
+
+    /// Starts searching the current position; the move found is played on the
+    /// shared board and sent to the GUI unless the board changed meanwhile.
+    fn think(&mut self) {
+        let (state, generation, limits) = {
+            let game = self.game.lock().unwrap();
+            (game.state.clone(), game.generation, self.limits(&game))
+        };
+        if game_result(&state).is_some() {
+            return;
+        }
+
+        let post = self.post;
+        let info_out = Arc::clone(&self.out);
+        let on_info = move |info: &SearchInfo| {
+            if post {
+                let mut out = info_out.lock().unwrap();
+                writeln!(out, "{}", thinking_output(info)).expect("writing output");
+                out.flush().expect("writing output");
+            }
+        };
+
+        let game = Arc::clone(&self.game);
+        let out = Arc::clone(&self.out);
+        let on_finish = move |best_move: Option<Move>| {
+            let mut game = game.lock().unwrap();
+            let Some(best_move) = best_move.filter(|_| game.generation == generation) else {
+                return;
+            };
+            game.state.make_move(best_move);
+            game.generation += 1;
+            let mut out = out.lock().unwrap();
+            writeln!(out, "move {best_move}").expect("writing output");
+            if let Some(result) = game_result(&game.state) {
+                writeln!(out, "{result}").expect("writing output");
+            }
+            out.flush().expect("writing output");
+        };
+
+        self.search.start(state, limits, on_info, on_finish);
+    }
+
+    fn limits(&self, game: &Game) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.max_depth,
+            movetime: self.move_time,
+            ..Default::default()
+        };
+        if self.move_time.is_none() {
+            let TimeControl {
+                moves_per_session,
+                base,
+                increment,
+            } = self.time_control;
+            let clock = Some(self.engine_clock.unwrap_or(base));
+            match game.state.side_to_move() {
+                Color::White => (limits.wtime, limits.winc) = (clock, Some(increment)),
+                Color::Black => (limits.btime, limits.binc) = (clock, Some(increment)),
+            }
+            if moves_per_session > 0 {
+                let played = game.session_moves() % moves_per_session;
+                limits.movestogo = Some(moves_per_session - played);
+            }
+        }
+        limits
+    }
+
+    fn take_back(&mut self, count: usize) {
+        self.abandon_search();
+        let mut game = self.game.lock().unwrap();
+        for _ in 0..count.min(game.state.moves_played().count()) {
+            game.state.unmake_move();
+        }
+        game.generation += 1;
+    }
+
+    fn reset(&mut self, state: State) {
+        self.abandon_search();
+        let mut game = self.game.lock().unwrap();
+        game.state = state;
+        game.generation += 1;
+        game.session_start = 0;
+    }
+
+    /// Stops the running search without playing the move it found.
+    fn abandon_search(&mut self) {
+        self.game.lock().unwrap().generation += 1;
+        self.search.stop();
+    }
+
+    fn error(&self, message: &str, command: &str) {
+        self.send(&format!("Error ({message}): {command}"));
+    }
+
+    fn send(&self, line: &str) {
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{line}").expect("writing output");
+        out.flush().expect("writing output");
+    }
+}
+
+/// Parses `level MPS BASE INC`, where BASE is minutes or `minutes:seconds` and
+/// INC is seconds.
+fn parse_level(arguments: &[&str]) -> Option<TimeControl> {
+    let [moves, base, increment] = arguments else {
+        return None;
+    };
+    let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+    let base = minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?;
+    Some(TimeControl {
+        moves_per_session: moves.parse().ok()?,
+        base: Duration::from_secs(base),
+        increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+    })
+}
+
+/// The thinking output line: ply, score in centipawns, time in centiseconds,
+/// nodes and the principal variation.
+fn thinking_output(info: &SearchInfo) -> String {
+    let score = match info.score {
+        Score::Centipawns(centipawns) => centipawns,
+        Score::Mate(moves) if moves > 0 => MATE_SCORE + moves,
+        Score::Mate(moves) => -MATE_SCORE + moves,
+    };
+    let pv: Vec<String> = info.pv.iter().map(Move::to_string).collect();
+    format!(
+        "{} {} {} {} {}",
+        info.depth,
+        score,
+        info.elapsed.as_millis() / 10,
+        info.nodes,
+        pv.join(" ")
+    )
+}
+
+/// The result command for a finished game, if the side to move is mated,
+/// stalemated or the fifty-move rule applies.
+fn game_result(state: &State) -> Option<&'static str> {
+    if state.legal_moves().is_empty() {
+        return Some(match (state.in_check(), state.side_to_move()) {
+            (true, Color::White) => "0-1 {Black mates}",
+            (true, Color::Black) => "1-0 {White mates}",
+            (false, _) => "1/2-1/2 {Stalemate}",
+        });
+    }
+    (state.halfmove_clock() >= 100).then_some("1/2-1/2 {Fifty move rule}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::SharedBuffer;
+
+    fn engine() -> (XBoard<SharedBuffer>, SharedBuffer) {
+        let buffer = SharedBuffer::default();
+        (XBoard::new(buffer.clone()), buffer)
+    }
+
+    fn fen(xboard: &XBoard<SharedBuffer>) -> String {
+        xboard.game.lock().unwrap().state.to_fen()
+    }
+
+    #[test]
+    fn test_feature_negotiation() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("xboard");
+        xboard.handle_command("protover 2");
+        xboard.handle_command("ping 7");
+        let lines = out.lines();
+        assert!(lines[0].starts_with("feature myname=\"pato"));
+        assert!(lines[0].contains("setboard=1") && lines[0].contains("usermove=1"));
+        assert!(lines[0].ends_with("done=1"));
+        assert_eq!(lines[1], "pong 7");
+    }
+
+    #[test]
+    fn test_engine_replies_to_user_move() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("new");
+        xboard.handle_command("sd 2");
+        xboard.handle_command("usermove e2e4");
+        xboard.search.wait();
+        let reply = out
+            .lines()
+            .last()
+            .unwrap()
+            .strip_prefix("move ")
+            .unwrap()
+            .to_string();
+
+        let mut state = State::new();
+        state.make_move(Move::from_uci("e2e4", &state).unwrap());
+        let reply = Move::from_uci(&reply, &state).unwrap();
+        assert!(state.legal_moves().contains(reply));
+        state.make_move(reply);
+        assert_eq!(fen(&xboard), state.to_fen());
+    }
+
+    #[test]
+    fn test_force_mode_and_go() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("new");
+        xboard.handle_command("force");
+        xboard.handle_command("usermove e2e4");
+        xboard.handle_command("e7e5");
+        assert!(out.lines().is_empty());
+        assert_eq!(
+            fen(&xboard),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+
+        xboard.handle_command("sd 2");
+        xboard.handle_command("go");
+        xboard.search.wait();
+        assert!(out.lines()[0].starts_with("move "));
+        assert_eq!(xboard.engine_color, Some(Color::White));
+    }
+
+    #[test]
+    fn test_post_thinking_output() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("post");
+        xboard.handle_command("setboard 7k/8/5KQ1/8/8/8/8/8 w - - 0 1");
+        xboard.handle_command("sd 2");
+        xboard.handle_command("go");
+        xboard.search.wait();
+        let lines = out.lines();
+        assert!(lines[0].starts_with("1 100001 "));
+        assert!(lines[1].starts_with("2 100001 "));
+        assert!(lines[2].starts_with("move "));
+        assert_eq!(lines[3], "1-0 {White mates}");
+    }
+
+    #[test]
+    fn test_illegal_move() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("force");
+        xboard.handle_command("usermove e2e5");
+        assert_eq!(out.lines(), ["Illegal move: e2e5"]);
+        assert_eq!(fen(&xboard), State::new().to_fen());
+    }
+
+    #[test]
+    fn test_setboard_undo_and_remove() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("force");
+        xboard.handle_command("setboard 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        xboard.handle_command("usermove e2e4");
+        xboard.handle_command("usermove e8d7");
+        xboard.handle_command("usermove e1d2");
+        xboard.handle_command("undo");
+        assert_eq!(fen(&xboard), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+        xboard.handle_command("remove");
+        assert_eq!(fen(&xboard), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+
+        xboard.handle_command("setboard 8/8/8 w - - 0 1");
+        assert!(out.lines()[0].starts_with("tellusererror Illegal position"));
+    }
+
+    #[test]
+    fn test_time_controls() {
+        let (mut xboard, _) = engine();
+        let limits_of = |xboard: &XBoard<_>| xboard.limits(&xboard.game.lock().unwrap());
+
+        xboard.handle_command("level 40 5 0");
+        xboard.handle_command("time 12000");
+        let limits = limits_of(&xboard);
+        assert_eq!(limits.wtime, Some(Duration::from_secs(120)));
+        assert_eq!(limits.movestogo, Some(40));
+
+        xboard.handle_command("level 0 2:30 1.5");
+        assert_eq!(
+            xboard.time_control,
+            TimeControl {
+                moves_per_session: 0,
+                base: Duration::from_secs(150),
+                increment: Duration::from_millis(1500),
+            }
+        );
+        let limits = limits_of(&xboard);
+        assert_eq!(limits.winc, Some(Duration::from_millis(1500)));
+        assert_eq!(limits.movestogo, None);
+
+        xboard.handle_command("st 3");
+        xboard.handle_command("sd 6");
+        let limits = limits_of(&xboard);
+        assert_eq!(limits.movetime, Some(Duration::from_secs(3)));
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(limits.wtime, None);
+    }
+
+    #[test]
+    fn test_moves_to_go_count_from_session_start() {
+        let (mut xboard, _) = engine();
+        xboard.handle_command("force");
+        xboard.handle_command("setboard 4k3/8/8/8/8/8/4P3/4K3 w - - 0 30");
+        xboard.handle_command("level 40 5 0");
+        let movestogo = |xboard: &XBoard<_>| xboard.limits(&xboard.game.lock().unwrap()).movestogo;
+        assert_eq!(movestogo(&xboard), Some(40));
+        xboard.handle_command("usermove e2e4");
+        assert_eq!(movestogo(&xboard), Some(40));
+        xboard.handle_command("usermove e8d7");
+        assert_eq!(movestogo(&xboard), Some(39));
+
+        // A session started mid-game counts from there.
+        xboard.handle_command("level 2 5 0");
+        assert_eq!(movestogo(&xboard), Some(2));
+        xboard.handle_command("usermove e1d2");
+        xboard.handle_command("usermove d7e6");
+        assert_eq!(movestogo(&xboard), Some(1));
+        xboard.handle_command("usermove d2e3");
+        xboard.handle_command("usermove e6d6");
+        assert_eq!(movestogo(&xboard), Some(2));
+    }
+
+    #[test]
+    fn test_thinking_output() {
+        let state = State::new();
+        let info = SearchInfo {
+            depth: 4,
+            score: Score::Centipawns(-35),
+            nodes: 12345,
+            elapsed: Duration::from_millis(1234),
+            pv: vec![
+                Move::from_uci("e2e4", &state).unwrap(),
+                Move::from_uci("g1f3", &state).unwrap(),
+            ],
+            hashfull: 0,
+        };
+        assert_eq!(thinking_output(&info), "4 -35 123 12345 e2e4 g1f3");
+        let mate = SearchInfo {
+            score: Score::Mate(3),
+            ..info.clone()
+        };
+        assert!(thinking_output(&mate).starts_with("4 100003 "));
+        let mated = SearchInfo {
+            score: Score::Mate(-2),
+            ..info
+        };
+        assert!(thinking_output(&mated).starts_with("4 -100002 "));
+    }
+
+    #[test]
+    fn test_game_result() {
+        let (mut xboard, out) = engine();
+        xboard.handle_command("force");
+        xboard.handle_command("setboard 7k/8/5KQ1/8/8/8/8/8 w - - 0 1");
+        xboard.handle_command("usermove g6g7");
+        assert_eq!(out.lines(), ["1-0 {White mates}"]);
+
+        let state = State::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game_result(&state), Some("1/2-1/2 {Stalemate}"));
+    }
+
+    #[test]
+    fn test_unknown_command_and_quit() {
+        let (mut xboard, out) = engine();
+        assert!(xboard.handle_command("frobnicate"));
+        assert_eq!(out.lines(), ["Error (unknown command): frobnicate"]);
+        assert!(!xboard.handle_command("quit"));
+    }
+}